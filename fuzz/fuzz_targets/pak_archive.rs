@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/pak_archive.rs"]
+#[allow(dead_code)]
+mod pak_archive;
+
+fuzz_target!(|data: &[u8]| {
+    // errors are fine, panics and runaway allocations are not
+    let source = std::io::Cursor::new(data);
+    let mut archive =
+        match pak_archive::PakArchive::with_max_entry_size(source, "<fuzz input>", 1 << 20) {
+            Ok(a) => a,
+            Err(_) => return,
+        };
+    let names: Vec<String> = archive.entries().iter().map(|e| e.name.clone()).collect();
+    for name in names {
+        let _ = archive.unpack(&name);
+    }
+});
@@ -0,0 +1,224 @@
+// Problems found while extracting. Anything that doesn't parse gets
+// reported and skipped, so one odd item from a new patch doesn't take the
+// whole run down with it.
+
+use crate::game_fs::GameFs;
+use roxmltree::{Document, Node};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    // the pak the file came from, after overrides
+    pub pak: Option<PathBuf>,
+    // path of the file in the game fs
+    pub file: Option<String>,
+    // e.g. `MechDefinition/ComponentList/Component[@Name=head]`
+    pub element: Option<String>,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            pak: None,
+            file: None,
+            element: None,
+            line: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(pak) = &self.pak {
+            write!(f, "{}: ", pak.display())?;
+        }
+        if let Some(file) = &self.file {
+            write!(f, "{}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+            }
+            write!(f, ": ")?;
+        }
+        if let Some(element) = &self.element {
+            write!(f, "<{}>: ", element)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.items.push(diagnostic);
+    }
+
+    // The value if there is one, otherwise the problem gets recorded.
+    pub fn ok<T>(&mut self, result: Result<T, Diagnostic>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(diagnostic) => {
+                self.push(diagnostic);
+                None
+            }
+        }
+    }
+
+    pub fn report(&self, mut out: impl std::io::Write) -> std::io::Result<()> {
+        if self.items.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            out,
+            "{} problem(s), the affected items were skipped:",
+            self.items.len()
+        )?;
+        for item in &self.items {
+            writeln!(out, "  {}", item)?;
+        }
+        Ok(())
+    }
+}
+
+// One xml file in the game fs, for pinning problems to where they are.
+pub struct XmlSource {
+    pak: Option<PathBuf>,
+    file: String,
+}
+
+impl XmlSource {
+    pub fn open(game_fs: &mut GameFs, file: &str) -> Result<(Self, Rc<[u8]>), Diagnostic> {
+        let source = Self {
+            pak: game_fs.source_of(file).map(|p| p.to_path_buf()),
+            file: file.to_string(),
+        };
+        let data = game_fs
+            .read(file)
+            .map_err(|e| source.error(e.to_string()))?;
+        Ok((source, data))
+    }
+
+    // Like `open`, but out of one particular pak, see `GameFs::read_from`.
+    pub fn open_in(
+        game_fs: &mut GameFs,
+        pak: &Path,
+        file: &str,
+    ) -> Result<(Self, Rc<[u8]>), Diagnostic> {
+        let source = Self {
+            pak: game_fs.archive(pak).map(|a| a.path().to_path_buf()),
+            file: file.to_string(),
+        };
+        let data = game_fs
+            .read_from(pak, file)
+            .map_err(|e| source.error(e.to_string()))?;
+        Ok((source, data))
+    }
+
+    pub fn parse<'input>(&self, data: &'input [u8]) -> Result<Document<'input>, Diagnostic> {
+        let text = std::str::from_utf8(data).map_err(|e| self.error(e.to_string()))?;
+        Document::parse(text).map_err(|e| self.error(e.to_string()))
+    }
+
+    // About the file as a whole.
+    pub fn error(&self, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            pak: self.pak.clone(),
+            file: Some(self.file.clone()),
+            ..Diagnostic::new(message)
+        }
+    }
+
+    pub fn error_at(&self, node: Node, message: impl Into<String>) -> Diagnostic {
+        let pos = node.document().text_pos_at(node.range().start);
+        Diagnostic {
+            element: Some(element_path(node)),
+            line: Some(pos.row),
+            ..self.error(message)
+        }
+    }
+
+    // The document's one top-level element, which has to be `tag`.
+    pub fn root<'a, 'input>(
+        &self,
+        doc: &'a Document<'input>,
+        tag: &str,
+    ) -> Result<Node<'a, 'input>, Diagnostic> {
+        let root = doc.root_element();
+        self.expect_tag(root, tag)?;
+        Ok(root)
+    }
+
+    pub fn expect_tag(&self, node: Node, tag: &str) -> Result<(), Diagnostic> {
+        if node.tag_name().name() != tag {
+            return Err(self.error_at(
+                node,
+                format!("expected <{}>, found <{}>", tag, node.tag_name().name()),
+            ));
+        }
+        Ok(())
+    }
+
+    // Child element called `tag`, of which there has to be exactly one.
+    pub fn only_child<'a, 'input>(
+        &self,
+        node: Node<'a, 'input>,
+        tag: &str,
+    ) -> Result<Node<'a, 'input>, Diagnostic> {
+        let mut found = node
+            .children()
+            .filter(|x| x.is_element() && x.tag_name().name() == tag);
+        match (found.next(), found.next()) {
+            (Some(child), None) => Ok(child),
+            (None, _) => Err(self.error_at(node, format!("no <{}> in here", tag))),
+            (Some(_), Some(second)) => {
+                Err(self.error_at(second, format!("more than one <{}>", tag)))
+            }
+        }
+    }
+
+    pub fn attr<'a>(&self, node: Node<'a, '_>, name: &str) -> Result<&'a str, Diagnostic> {
+        node.attribute(name)
+            .ok_or_else(|| self.error_at(node, format!("missing attribute {:?}", name)))
+    }
+
+    pub fn parse_attr<T>(&self, node: Node, name: &str) -> Result<T, Diagnostic>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let value = self.attr(node, name)?;
+        value.parse().map_err(|e| {
+            self.error_at(
+                node,
+                format!("attribute {}={:?} doesn't parse: {}", name, value, e),
+            )
+        })
+    }
+}
+
+// Tag names from the root down, with whatever identifies each element.
+fn element_path(node: Node) -> String {
+    let mut parts: Vec<String> = node
+        .ancestors()
+        .filter(|n| n.is_element())
+        .map(|n| {
+            let tag = n.tag_name().name();
+            let key = ["id", "name", "Name", "ID"]
+                .iter()
+                .find_map(|k| n.attribute(*k).map(|v| (k, v)));
+            match key {
+                Some((k, v)) => format!("{}[@{}={}]", tag, k, v),
+                None => tag.to_string(),
+            }
+        })
+        .collect();
+    parts.reverse();
+    parts.join("/")
+}
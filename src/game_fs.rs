@@ -0,0 +1,388 @@
+use crate::pak_archive::{normalize_name, PakArchive, PakEntry, PakError};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+type Pak = PakArchive<std::io::BufReader<std::fs::File>>;
+
+// How many bytes of unpacked files to keep around by default. Comfortably
+// fits the big GameData xmls that several passes want.
+const DEFAULT_CACHE_LIMIT: usize = 64 << 20;
+
+struct MountedPak {
+    // relative to the Game/ directory
+    rel_path: PathBuf,
+    archive: Pak,
+}
+
+// All the paks under Game/ seen as one file tree, the way the game sees them.
+//
+// Mount order: archives go in by their path relative to Game/ (ignoring case),
+// except that anything named `patch*.pak` goes after all the others. When two
+// archives have the same file, the one mounted later wins.
+pub struct GameFs {
+    root: PathBuf,
+    paks: Vec<MountedPak>,
+    // normalized name -> (index into `paks`, name as stored in that pak)
+    files: HashMap<String, (usize, String)>,
+    mount_errors: Vec<PakError>,
+    name_conflicts: Vec<NameConflict>,
+    cache: EntryCache,
+}
+
+// Files that end up under the same name, ignoring case and slashes, when
+// that's not just a patch pak overriding something on purpose. Only the last
+// one mounted can be read.
+#[derive(Debug)]
+pub struct NameConflict {
+    pub pak: PathBuf,
+    pub name: String,
+    // None when both are in `pak`
+    pub other_pak: Option<PathBuf>,
+    pub other_name: String,
+}
+
+impl std::fmt::Display for NameConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.other_pak {
+            None => write!(
+                f,
+                "{:?} and {:?} only differ in case or slashes in {}",
+                self.other_name,
+                self.name,
+                self.pak.display()
+            ),
+            Some(other_pak) => write!(
+                f,
+                "{:?} in {} hides {:?} in {}",
+                self.name,
+                self.pak.display(),
+                self.other_name,
+                other_pak.display()
+            ),
+        }
+    }
+}
+
+impl GameFs {
+    pub fn mount(game_dir: impl AsRef<Path>) -> Result<Self, PakError> {
+        let root = game_dir.as_ref().to_path_buf();
+        let mut mount_errors = vec![];
+        let mut name_conflicts = vec![];
+        let mut pak_paths = vec![];
+        find_paks(&root, &mut pak_paths).map_err(|e| PakError::Io {
+            archive: root.clone(),
+            entry: None,
+            source: e,
+        })?;
+        pak_paths.sort_by_cached_key(|p| {
+            let (is_patch, rel_path) = mount_order(&root, p);
+            (is_patch, numbers_padded(&rel_path))
+        });
+
+        let mut paks: Vec<MountedPak> = vec![];
+        let mut files = HashMap::new();
+        for pak_path in pak_paths {
+            let archive = match PakArchive::from_file(&pak_path) {
+                Ok(a) => a,
+                Err(e) => {
+                    // one broken pak shouldn't take the rest of the game with it
+                    mount_errors.push(e);
+                    continue;
+                }
+            };
+            for names in archive.ambiguous_names() {
+                for pair in names.windows(2) {
+                    name_conflicts.push(NameConflict {
+                        pak: pak_path.clone(),
+                        name: pair[1].to_string(),
+                        other_pak: None,
+                        other_name: pair[0].to_string(),
+                    });
+                }
+            }
+            let (is_patch, rel_path) = mount_order(&root, &pak_path);
+            // one per language, with the same files in each
+            let is_language = rel_path.starts_with("localized/");
+            for entry in archive.entries() {
+                let previous = files.insert(
+                    normalize_name(&entry.name),
+                    (paks.len(), entry.name.clone()),
+                );
+                match previous {
+                    // same pak, ambiguous_names has it already
+                    Some((idx, _)) if idx == paks.len() => {}
+                    Some((idx, other_name)) if !is_patch && !is_language => {
+                        name_conflicts.push(NameConflict {
+                            pak: pak_path.clone(),
+                            name: entry.name.clone(),
+                            other_pak: Some(paks[idx].archive.path().to_path_buf()),
+                            other_name,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            paks.push(MountedPak {
+                rel_path: pak_path.strip_prefix(&root).unwrap().to_path_buf(),
+                archive,
+            });
+        }
+
+        Ok(Self {
+            root,
+            paks,
+            files,
+            mount_errors,
+            name_conflicts,
+            cache: EntryCache::new(DEFAULT_CACHE_LIMIT),
+        })
+    }
+
+    // Paks that were found but couldn't be mounted.
+    pub fn mount_errors(&self) -> &[PakError] {
+        &self.mount_errors
+    }
+
+    pub fn name_conflicts(&self) -> &[NameConflict] {
+        &self.name_conflicts
+    }
+
+    // Recently read files come out of a cache rather than being unpacked again.
+    pub fn read(&mut self, path: &str) -> Result<Rc<[u8]>, PakError> {
+        let key = normalize_name(path);
+        if let Some(data) = self.cache.get(&key) {
+            return Ok(data);
+        }
+        let (pak_idx, name) = self.files.get(&key).ok_or_else(|| PakError::MissingEntry {
+            archive: self.root.clone(),
+            entry: path.to_string(),
+        })?;
+        let data: Rc<[u8]> = self.paks[*pak_idx].archive.unpack(name)?.into();
+        self.cache.insert(key, data.clone());
+        Ok(data)
+    }
+
+    // In bytes of unpacked data. 0 turns the cache off.
+    pub fn set_cache_limit(&mut self, limit: usize) {
+        self.cache = EntryCache::new(limit);
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(&normalize_name(path))
+    }
+
+    // Everything in the `dir` folder and below, with the names as stored in
+    // the paks.
+    pub fn list(&self, dir: &str) -> Vec<&str> {
+        let mut prefix = normalize_name(dir);
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        let mut result: Vec<&str> = self
+            .files
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, (_, name))| name.as_str())
+            .collect();
+        result.sort_unstable();
+        result
+    }
+
+    // Every file the game sees, after overrides. In no particular order.
+    pub fn entries(&self) -> Vec<&PakEntry> {
+        self.files
+            .values()
+            .filter_map(|(pak_idx, name)| self.paks[*pak_idx].archive.entry(name).ok())
+            .collect()
+    }
+
+    // Which pak `path` actually comes from, after overrides.
+    pub fn source_of(&self, path: &str) -> Option<&Path> {
+        self.files
+            .get(&normalize_name(path))
+            .map(|(pak_idx, _)| self.paks[*pak_idx].archive.path())
+    }
+
+    // A single mounted pak, by its path relative to Game/. Reading through
+    // `read` rather than through the archive keeps the overrides in effect.
+    pub fn archive(&self, rel_path: impl AsRef<Path>) -> Option<&Pak> {
+        let idx = self.pak_index(rel_path.as_ref())?;
+        Some(&self.paks[idx].archive)
+    }
+
+    // Paths relative to Game/ of the mounted paks in `dir`, in mount order.
+    pub fn paks_in(&self, dir: impl AsRef<Path>) -> Vec<&Path> {
+        let wanted = normalize_name(&dir.as_ref().to_string_lossy());
+        self.paks
+            .iter()
+            .map(|p| p.rel_path.as_path())
+            .filter(|rel_path| {
+                let parent = rel_path.parent().unwrap_or_else(|| Path::new(""));
+                normalize_name(&parent.to_string_lossy()) == wanted
+            })
+            .collect()
+    }
+
+    // `path` out of one particular pak, whatever overrides it. For paks that
+    // all have the same file names, like the language ones. Not cached.
+    pub fn read_from(
+        &mut self,
+        rel_path: impl AsRef<Path>,
+        path: &str,
+    ) -> Result<Rc<[u8]>, PakError> {
+        let rel_path = rel_path.as_ref();
+        let idx = self
+            .pak_index(rel_path)
+            .ok_or_else(|| PakError::MissingEntry {
+                archive: self.root.join(rel_path),
+                entry: path.to_string(),
+            })?;
+        Ok(self.paks[idx].archive.unpack(path)?.into())
+    }
+
+    fn pak_index(&self, rel_path: &Path) -> Option<usize> {
+        let wanted = normalize_name(&rel_path.to_string_lossy());
+        self.paks
+            .iter()
+            .position(|p| normalize_name(&p.rel_path.to_string_lossy()) == wanted)
+    }
+}
+
+// Least recently used files get dropped first once over the limit.
+struct EntryCache {
+    limit: usize,
+    size: usize,
+    // key -> (when it was last used, data)
+    entries: HashMap<String, (u64, Rc<[u8]>)>,
+    // every use, oldest first. A hit only adds a new one, so it stays cheap,
+    // and the older uses of that key get skipped when dropping
+    uses: VecDeque<(u64, String)>,
+    clock: u64,
+}
+
+impl EntryCache {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            size: 0,
+            entries: HashMap::new(),
+            uses: VecDeque::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Rc<[u8]>> {
+        let now = self.clock + 1;
+        let (last_used, data) = self.entries.get_mut(key)?;
+        *last_used = now;
+        let data = data.clone();
+        self.clock = now;
+        self.uses.push_back((now, key.to_string()));
+        if self.uses.len() > 2 * self.entries.len() + 16 {
+            // mostly stale by now
+            let entries = &self.entries;
+            self.uses
+                .retain(|(time, k)| matches!(entries.get(k), Some((t, _)) if t == time));
+        }
+        Some(data)
+    }
+
+    fn insert(&mut self, key: String, data: Rc<[u8]>) {
+        if data.len() > self.limit || self.entries.contains_key(&key) {
+            return;
+        }
+        self.clock += 1;
+        self.size += data.len();
+        self.uses.push_back((self.clock, key.clone()));
+        self.entries.insert(key, (self.clock, data));
+        while self.size > self.limit {
+            let (time, oldest) = match self.uses.pop_front() {
+                Some(u) => u,
+                None => break,
+            };
+            if matches!(self.entries.get(&oldest), Some((t, _)) if *t == time) {
+                let (_, data) = self.entries.remove(&oldest).unwrap();
+                self.size -= data.len();
+            }
+        }
+    }
+}
+
+fn find_paks(dir: &Path, result: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_paks(&path, result)?;
+        } else if matches!(path.extension(), Some(ext) if ext.eq_ignore_ascii_case("pak")) {
+            result.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn mount_order(root: &Path, pak_path: &Path) -> (bool, String) {
+    let rel_path = normalize_name(&pak_path.strip_prefix(root).unwrap().to_string_lossy());
+    let is_patch = matches!(
+        pak_path.file_stem(),
+        Some(stem) if stem.to_string_lossy().to_lowercase().starts_with("patch")
+    );
+    (is_patch, rel_path)
+}
+
+// Zero-pads every run of digits, so the names sort by number: `patch_10.pak`
+// goes after `patch_2.pak`, and wins over it.
+fn numbers_padded(name: &str) -> String {
+    const WIDTH: usize = 20;
+    let mut result = String::with_capacity(name.len());
+    let mut digits = String::new();
+    for c in name.chars().chain(std::iter::once('\0')) {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        if !digits.is_empty() {
+            for _ in digits.len()..WIDTH {
+                result.push('0');
+            }
+            result.push_str(&digits);
+            digits.clear();
+        }
+        if c != '\0' {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches_sort_by_number() {
+        let mut names = vec!["patch_10.pak", "patch_2.pak", "patch.pak", "patch_1.pak"];
+        names.sort_by_key(|n| numbers_padded(n));
+        assert_eq!(
+            names,
+            ["patch.pak", "patch_1.pak", "patch_2.pak", "patch_10.pak"]
+        );
+    }
+
+    #[test]
+    fn cache_drops_least_recently_used() {
+        let data = |n: usize| -> Rc<[u8]> { vec![0; n].into() };
+        let mut cache = EntryCache::new(10);
+        cache.insert("a".to_string(), data(4));
+        cache.insert("b".to_string(), data(4));
+        for _ in 0..100 {
+            assert!(cache.get("a").is_some());
+        }
+        assert!(cache.uses.len() < 100);
+        cache.insert("c".to_string(), data(4));
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.size, 8);
+    }
+}
@@ -19,6 +19,9 @@ use yew::{html, Component, ComponentLink, Html, ShouldRender};
 
 type AsBinary = bool;
 
+// what the names get shown in
+const LANGUAGE: &str = "english";
+
 pub enum Format {
     Json,
     Toml,
@@ -97,8 +100,8 @@ pub struct Model {
 }
 
 impl Model {
-    fn view_weapon_select(&self, weapons: &[mwo_types::Weapon]) -> Html {
-        let mut weapons = weapons.to_vec();
+    fn view_weapon_select(&self, data: &mwo_types::MechdataCombined2) -> Html {
+        let mut weapons = data.weapons.to_vec();
         weapons.sort_by_key(|w| {
             (
                 w.faction.to_owned(),
@@ -120,7 +123,7 @@ impl Model {
                     {std::iter::once(
                         html!{ <option hidden=true disabled=true selected=true></option> }
                     ).chain(weapons.iter().map(|w|
-                        html!{ <option> {w.name.as_str()}</option> }
+                        html!{ <option value=w.name.clone()> {data.display_name(LANGUAGE, &w.name)}</option> }
                     )).collect::<Html>() }
                 </select>
                 <input type="number" min="0" max="20" value={self.chosen_weapon_amt}
@@ -174,7 +177,7 @@ impl Model {
             .iter()
             .map(|m| (m.variant_name.clone(), m.clone()))
             .collect();
-        let mechs_quirked = some::stuffs(&data.weapons, &mech_variants, &mech_map, weapon, amt);
+        let mechs_quirked = some::stuffs(&data.weapons, &data.engines, &data.upgrades, &mech_variants, &mech_map, weapon, amt);
         let mut some_mechs = mech_variants.clone();
         some_mechs.sort_by_key(|m| m.max_tons);
         ConsoleService::log("hello");
@@ -220,7 +223,7 @@ impl Model {
             <tr class={class}>
                 <td>{ mech.max_tons }</td>
                 <td>{ &mech.chassis }</td>
-                <td>{ &mech.variant_name }</td>
+                <td>{ data.display_name(LANGUAGE, &mech.variant_name) }</td>
                 {
                     quirk_keys_present.iter().map(|key: &&String| {
                         let key: &str = key;
@@ -280,7 +283,7 @@ impl Model {
 
         html! {
            <div>
-                { self.view_weapon_select(&data.weapons) }
+                { self.view_weapon_select(data) }
                 { self.view_checkboxes() }
                 {
                     if let Some(weap) = &self.chosen_weapon{
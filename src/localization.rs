@@ -0,0 +1,171 @@
+// User-facing names and descriptions, out of the string tables under
+// Game/Localized. There is one pak per language (`English_xml.pak` and so
+// on), all with the same file names inside, each an Excel 2003 xml workbook.
+//
+// Keys look like `@ClanERLargeLaser`, with the description under
+// `@ClanERLargeLaser_desc`. Case doesn't matter.
+
+use crate::diagnostics::{Diagnostic, Diagnostics, XmlSource};
+use crate::game_fs::GameFs;
+use crate::mwo_types::{LocalizedText, MechdataCombined2};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+const SPREADSHEET_NS: &str = "urn:schemas-microsoft-com:office:spreadsheet";
+
+// Fills in `data.localized` for everything in `data` that has a name to show.
+pub fn localize(game_fs: &mut GameFs, data: &mut MechdataCombined2, diags: &mut Diagnostics) {
+    let wanted = wanted_names(data);
+    let paks: Vec<PathBuf> = game_fs
+        .paks_in("Localized")
+        .into_iter()
+        .map(Path::to_path_buf)
+        .collect();
+
+    for pak in paks {
+        // `English_xml.pak` -> `english`
+        let language = pak
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        let language = language
+            .split('_')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let files: Vec<String> = match game_fs.archive(&pak) {
+            Some(archive) => archive.glob("*.xml").map(|e| e.name.clone()).collect(),
+            None => continue,
+        };
+
+        // lowercase key without the `@` -> text
+        let mut strings = HashMap::new();
+        for file in files {
+            let table = XmlSource::open_in(game_fs, &pak, &file).and_then(|(src, data)| {
+                let doc = src.parse(&data)?;
+                let workbook = src.root(&doc, "Workbook")?;
+                for worksheet in workbook
+                    .children()
+                    .filter(|x| x.is_element() && x.tag_name().name() == "Worksheet")
+                {
+                    strings.extend(
+                        diags
+                            .ok(parse_worksheet(&src, worksheet))
+                            .unwrap_or_default(),
+                    );
+                }
+                Ok(())
+            });
+            diags.ok(table);
+        }
+
+        let texts = wanted.iter().filter_map(|name| {
+            let key = name.to_lowercase();
+            let text = LocalizedText {
+                name: strings.get(&key)?.clone(),
+                description: strings.get(&format!("{}_desc", key)).cloned(),
+            };
+            Some((name.clone(), text))
+        });
+        data.localized.entry(language).or_default().extend(texts);
+    }
+}
+
+fn wanted_names(data: &MechdataCombined2) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    names.extend(data.weapons.iter().map(|w| w.name.clone()));
+    names.extend(data.ammo.iter().map(|a| a.name.clone()));
+    names.extend(data.engines.iter().map(|e| e.name.clone()));
+    names.extend(data.equipment.iter().map(|e| e.name.clone()));
+    names.extend(data.upgrades.iter().map(|u| u.name.clone()));
+    for variant in &data.mech_variants {
+        names.insert(variant.chassis.clone());
+        names.insert(variant.variant_name.clone());
+        names.extend(variant.quirks.iter().map(|(q, _)| q.clone()));
+    }
+    for pod in &data.omnipods {
+        names.extend(pod.quirks.iter().map(|(q, _)| q.clone()));
+    }
+    for set in &data.omnipod_sets {
+        for quirks in set.bonuses.values() {
+            names.extend(quirks.iter().map(|(q, _)| q.clone()));
+        }
+    }
+    names
+}
+
+// Key and text of every row. The first row names the columns: there's always
+// a KEY one, then the text is under TRANSLATED TEXT, or ORIGINAL TEXT in the
+// English tables and wherever the translation is missing.
+fn parse_worksheet(
+    src: &XmlSource,
+    worksheet: roxmltree::Node,
+) -> Result<Vec<(String, String)>, Diagnostic> {
+    let table = src.only_child(worksheet, "Table")?;
+    let mut rows = table
+        .children()
+        .filter(|x| x.is_element() && x.tag_name().name() == "Row");
+    let header = match rows.next() {
+        Some(row) => row_cells(src, row)?,
+        None => return Ok(vec![]),
+    };
+    let column = |title: &str| {
+        header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(title))
+    };
+    let key_column = column("KEY").ok_or_else(|| src.error_at(table, "no KEY column"))?;
+    let original_column = column("ORIGINAL TEXT");
+    let translated_column = column("TRANSLATED TEXT");
+    if original_column.is_none() && translated_column.is_none() {
+        return Err(src.error_at(table, "no text column"));
+    }
+
+    let mut strings = vec![];
+    for row in rows {
+        let cells = row_cells(src, row)?;
+        let cell = |column: Option<usize>| {
+            column
+                .and_then(|c| cells.get(c))
+                .map(|text| text.trim())
+                .filter(|text| !text.is_empty())
+        };
+        let key = match cell(Some(key_column)) {
+            Some(key) => key,
+            None => continue,
+        };
+        let text = match cell(translated_column).or_else(|| cell(original_column)) {
+            Some(text) => text,
+            None => continue,
+        };
+        strings.push((key.trim_start_matches('@').to_lowercase(), text.to_string()));
+    }
+    Ok(strings)
+}
+
+// The text in each column. A cell can skip ahead with `ss:Index` (counting
+// from 1), leaving the columns in between empty.
+fn row_cells(src: &XmlSource, row: roxmltree::Node) -> Result<Vec<String>, Diagnostic> {
+    let mut cells = vec![];
+    for cell in row
+        .children()
+        .filter(|x| x.is_element() && x.tag_name().name() == "Cell")
+    {
+        if let Some(index) = cell.attribute((SPREADSHEET_NS, "Index")) {
+            match index.parse::<usize>() {
+                Ok(i) if i > cells.len() => cells.resize(i - 1, String::new()),
+                _ => return Err(src.error_at(cell, format!("bad ss:Index {:?}", index))),
+            }
+        }
+        // formatted text comes as html inside the <Data>
+        let text: String = cell
+            .children()
+            .filter(|x| x.is_element() && x.tag_name().name() == "Data")
+            .flat_map(|data| data.descendants())
+            .filter(|n| n.is_text())
+            .filter_map(|n| n.text())
+            .collect();
+        cells.push(text);
+    }
+    Ok(cells)
+}
@@ -10,6 +10,120 @@ pub struct Weapon {
     pub id: i32,
     pub cooldown: f32,
     pub speed: i32,
+
+    pub damage: f32,
+    pub heat: f32,
+    // how long a beam is on for, 0 for everything that isn't a laser
+    pub duration: f32,
+    pub min_range: f32,
+    pub long_range: f32,
+    pub max_range: f32,
+    // ghost heat: weapons sharing an id group together, and firing more than
+    // `min_heat_penalty_level` of them at once costs `heat_penalty` extra
+    pub heat_penalty: f32,
+    pub heat_penalty_id: Option<i32>,
+    pub min_heat_penalty_level: Option<i32>,
+    pub ammo_type: Option<String>,
+    pub ammo_per_shot: i32,
+    // missiles in a volley
+    pub num_firing: i32,
+    // projectiles per shot, like LB-X pellets
+    pub num_per_shot: i32,
+    pub spread: f32,
+    pub jamming_chance: f32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Ammo {
+    pub name: String,
+    pub id: i32,
+    pub faction: Affiliation,
+    // what weapons refer to it by, see `Weapon::ammo_type`. The half ton
+    // versions share this with the full ton ones
+    pub ammo_type: String,
+    pub slots: i32,
+    pub tons: f32,
+    pub health: f32,
+    // in the whole item, so a half ton has about half as many
+    pub rounds: i32,
+    pub rounds_per_ton: f32,
+    // ids of the weapons that shoot it
+    pub weapon_ids: Vec<i32>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Engine {
+    pub name: String,
+    pub id: i32,
+    pub faction: Affiliation,
+    // None if the name doesn't say
+    pub kind: Option<EngineKind>,
+    pub rating: i32,
+    // as the game has it, side torso parts included
+    pub tons: f32,
+    // in the centre torso
+    pub slots: i32,
+    // in each side torso
+    pub side_slots: i32,
+    // come with the engine, taking up neither tons nor slots
+    pub heat_sinks: i32,
+    // how many more heat sinks fit inside the engine itself
+    pub heat_sink_slots: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EngineKind {
+    Std,
+    Xl,
+    Light,
+    ClanXl,
+}
+
+// Heat sinks, jump jets, ECM, CASE and the like.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Equipment {
+    pub name: String,
+    pub id: i32,
+    pub faction: Affiliation,
+    // named after the stats the game gives it: "HeatSink", "JumpJet", "ECM"...
+    pub kind: String,
+    pub slots: i32,
+    pub tons: f32,
+    pub health: f32,
+    // component names, empty if it can go anywhere
+    pub allowed_locations: Vec<String>,
+    // everything numeric from its kind-specific stats, e.g. `cooling` for heat
+    // sinks or `minTons`/`maxTons` for jump jets
+    pub stats: BTreeMap<String, f32>,
+}
+
+// Structure, armor and heat sink upgrades. A mech has exactly one of each.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Upgrade {
+    pub name: String,
+    pub id: i32,
+    pub faction: Affiliation,
+    // spread over the mech however the player likes
+    pub slots: i32,
+    pub kind: UpgradeKind,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub enum UpgradeKind {
+    // the structure weighs this much per ton of the mech's max tonnage
+    Structure {
+        kind: StructureType,
+        tons_per_mech_ton: f32,
+    },
+    Armor {
+        kind: ArmorType,
+        armor_per_ton: f32,
+    },
+    // the heat sinks the mech gets, engine ones included
+    HeatSinks {
+        kind: HSType,
+        heat_sink_id: i32,
+    },
 }
 
 #[derive(
@@ -19,21 +133,23 @@ pub enum Affiliation {
     InnerSphere,
     Clan,
 }
-#[derive(Debug, Clone, serde::Deserialize, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum StructureType {
     Std,
     Endo,
 }
-#[derive(Debug, Clone, serde::Deserialize, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub enum ArmorType {
     Std,
     Ferro,
     Stealth,
     LightFerro,
+    Reactive,
+    Reflective,
 }
-#[derive(Debug, Clone, serde::Deserialize, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum HSType {
     Single,
@@ -85,6 +201,65 @@ pub struct Variant {
 
     pub components: BTreeMap<String, Component>,
     pub quirks: Vec<(String, f32)>,
+    // None if the mdf's is missing or broken
+    pub movement: Option<Movement>,
+    // None if the mdf doesn't come with one
+    pub loadout: Option<Loadout>,
+}
+
+// Base values the mobility quirks apply to. Angles are in degrees, turn speeds
+// in degrees per second.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Movement {
+    // "Small", "Huge" and so on, sets how hills slow the mech down
+    pub archetype: String,
+    // scales the top speed the engine gives
+    pub max_speed_factor: f32,
+    pub reverse_speed_multiplier: f32,
+    pub torso_yaw_speed: f32,
+    pub torso_pitch_speed: f32,
+    pub arm_yaw_speed: f32,
+    pub arm_pitch_speed: f32,
+    pub max_torso_yaw: f32,
+    pub max_torso_pitch: f32,
+    pub max_arm_yaw: f32,
+    pub max_arm_pitch: f32,
+    pub turn_rate: SpeedCurve,
+    pub acceleration: SpeedCurve,
+    pub deceleration: SpeedCurve,
+}
+
+// A rate that depends on how fast the mech is going, interpolated between
+// three points. Speeds are fractions of the top speed.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SpeedCurve {
+    pub low_speed: f32,
+    pub mid_speed: f32,
+    pub high_speed: f32,
+    pub low_rate: f32,
+    pub mid_rate: f32,
+    pub high_rate: f32,
+}
+
+// What a variant comes with out of the factory.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Loadout {
+    pub engine_id: Option<i32>,
+    pub structure_id: i32,
+    pub armor_id: i32,
+    pub heat_sinks_id: i32,
+    // keyed like `Variant::components`
+    pub components: BTreeMap<String, LoadoutComponent>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct LoadoutComponent {
+    pub armor: i32,
+    // only torsos have any
+    pub rear_armor: i32,
+    pub weapon_ids: Vec<i32>,
+    // ammo, heat sinks, jump jets and the like, but not the engine
+    pub module_ids: Vec<i32>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -96,11 +271,75 @@ pub struct Component {
     // kind -> count
     pub hardpoint_count: BTreeMap<u8, i32>,
     pub can_equip_ecm: bool,
+    // the hardpoints that have doors over them
+    pub weapon_doors: Vec<WeaponDoor>,
+}
+
+// Bay doors over a hardpoint. What's behind them can only fire once they're
+// open. Times are in seconds.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct WeaponDoor {
+    pub hardpoint_id: i32,
+    // as in `Component::hardpoint_count`
+    pub hardpoint_kind: u8,
+    pub door_set_id: i32,
+    pub open_duration: f32,
+    pub close_duration: f32,
+    // they stay open this long after the last shot
+    pub delay_before_close: f32,
+}
+
+// A swappable part of a Clan OmniMech. Putting it on a mech of the same
+// chassis replaces that mech's component at `location` with `component`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct OmniPod {
+    pub chassis: String,
+    // the set it is part of, named after the variant that comes with it
+    pub set: String,
+    pub location: String,
+    // slots and hp are the chassis', the rest is the pod's own
+    pub component: Component,
+    pub quirks: Vec<(String, f32)>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct OmniPodSet {
+    pub chassis: String,
+    pub name: String,
+    // pieces of the set equipped -> quirks it gives, from 2 to 8
+    pub bonuses: BTreeMap<i32, Vec<(String, f32)>>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct LocalizedText {
+    pub name: String,
+    pub description: Option<String>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct MechdataCombined2 {
     pub weapons: Vec<Weapon>,
+    pub ammo: Vec<Ammo>,
+    pub engines: Vec<Engine>,
+    pub equipment: Vec<Equipment>,
+    pub upgrades: Vec<Upgrade>,
     pub mech_variants: Vec<Variant>,
+    pub omnipods: Vec<OmniPod>,
+    pub omnipod_sets: Vec<OmniPodSet>,
+    // language (lowercase) -> internal name -> text. The internal names are
+    // weapon, item, upgrade, chassis and variant names, and quirks
+    pub localized: BTreeMap<String, BTreeMap<String, LocalizedText>>,
+}
+
+impl MechdataCombined2 {
+    // What to show for `key` in `language`, or `key` itself if there is no
+    // translation.
+    #[allow(dead_code)]
+    pub fn display_name<'a>(&'a self, language: &str, key: &'a str) -> &'a str {
+        self.localized
+            .get(&language.to_lowercase())
+            .and_then(|texts| texts.get(key))
+            .map_or(key, |text| text.name.as_str())
+    }
 }
@@ -1,100 +1,840 @@
-use std::io::Read;
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 
-struct IndexEntry {
-    filename: String,
-    data_offset: u64,
-    data_len: u64,
+pub(crate) const LOCAL_HEADER_MAGIC: &[u8] = b"PK\x03\x04";
+pub(crate) const CENTRAL_HEADER_MAGIC: &[u8] = b"PK\x01\x02";
+pub(crate) const END_OF_CENTRAL_DIR_MAGIC: &[u8] = b"PK\x05\x06";
+pub(crate) const ZIP64_END_OF_CENTRAL_DIR_MAGIC: &[u8] = b"PK\x06\x06";
+pub(crate) const ZIP64_LOCATOR_MAGIC: &[u8] = b"PK\x06\x07";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    Stored,
+    Deflate,
+    Unsupported(u16),
+}
+impl Compression {
+    fn from_method(method: u16) -> Self {
+        match method {
+            0 => Compression::Stored,
+            8 => Compression::Deflate,
+            other => Compression::Unsupported(other),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PakError {
+    #[error("{entry:?} not present in {}", .archive.display())]
+    MissingEntry { archive: PathBuf, entry: String },
+    #[error("{entry:?} could be any of {candidates:?} in {}", .archive.display())]
+    Ambiguous {
+        archive: PathBuf,
+        entry: String,
+        candidates: Vec<String>,
+    },
+    #[error("unexpected bytes {found:02x?} at offset {offset} in {}{}", .archive.display(), in_entry(.entry))]
+    BadMagic {
+        archive: PathBuf,
+        entry: Option<String>,
+        offset: u64,
+        found: Vec<u8>,
+    },
+    #[error("{} is truncated{}", .archive.display(), in_entry(.entry))]
+    Truncated {
+        archive: PathBuf,
+        entry: Option<String>,
+    },
+    #[error("failed to decompress {entry:?} in {}: {source}", .archive.display())]
+    Decompress {
+        archive: PathBuf,
+        entry: String,
+        source: std::io::Error,
+    },
+    #[error("{entry:?} in {} unpacked to {actual} bytes, expected {expected}", .archive.display())]
+    SizeMismatch {
+        archive: PathBuf,
+        entry: String,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("{entry:?} in {} has crc {actual:08x}, expected {expected:08x}", .archive.display())]
+    CrcMismatch {
+        archive: PathBuf,
+        entry: String,
+        expected: u32,
+        actual: u32,
+    },
+    #[error("{} is malformed{}: {reason}", .archive.display(), in_entry(.entry))]
+    Malformed {
+        archive: PathBuf,
+        entry: Option<String>,
+        reason: String,
+    },
+    #[error("{entry:?} in {} would unpack to {size} bytes, over the limit of {limit}", .archive.display())]
+    TooLarge {
+        archive: PathBuf,
+        entry: String,
+        size: u64,
+        limit: u64,
+    },
+    #[error("failed to read {}{}: {source}", .archive.display(), in_entry(.entry))]
+    Io {
+        archive: PathBuf,
+        entry: Option<String>,
+        source: std::io::Error,
+    },
+}
+impl PakError {
+    fn from_io(archive: &Path, entry: Option<&str>, err: std::io::Error) -> Self {
+        let archive = archive.to_path_buf();
+        let entry = entry.map(|e| e.to_string());
+        if err.kind() == std::io::ErrorKind::UnexpectedEof {
+            return PakError::Truncated { archive, entry };
+        }
+        if let Some(magic) = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<UnexpectedMagic>())
+        {
+            return PakError::BadMagic {
+                archive,
+                entry,
+                offset: magic.offset,
+                found: magic.found.clone(),
+            };
+        }
+        if let Some(Malformed(reason)) = err.get_ref().and_then(|e| e.downcast_ref()) {
+            return PakError::Malformed {
+                archive,
+                entry,
+                reason: reason.clone(),
+            };
+        }
+        PakError::Io {
+            archive,
+            entry,
+            source: err,
+        }
+    }
+}
+fn in_entry(entry: &Option<String>) -> String {
+    match entry {
+        Some(e) => format!(" (reading {:?})", e),
+        None => "".to_string(),
+    }
+}
+
+// Raised from deep inside the parsing as an io error, then turned into
+// `PakError::BadMagic` once we know which archive we're in.
+#[derive(Debug, thiserror::Error)]
+#[error("unexpected bytes {found:02x?} at offset {offset}")]
+struct UnexpectedMagic {
+    offset: u64,
+    found: Vec<u8>,
+}
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct Malformed(String);
+fn malformed(reason: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, Malformed(reason))
+}
+
+fn check_magic(found: Vec<u8>, expected: &[u8], offset: u64) -> std::io::Result<()> {
+    if found.as_slice() == expected {
+        return Ok(());
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        UnexpectedMagic { offset, found },
+    ))
+}
+
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+
+const LOCAL_HEADER_LEN: u64 = 30;
+const CENTRAL_HEADER_LEN: u64 = 46;
+const END_OF_CENTRAL_DIR_LEN: u64 = 22;
+const ZIP64_END_OF_CENTRAL_DIR_LEN: u64 = 56;
+const ZIP64_LOCATOR_LEN: u64 = 20;
+pub(crate) const ZIP64_EXTRA_ID: u16 = 0x0001;
+// what a 32-bit field is set to when the real value is in the zip64 extra field
+pub(crate) const ZIP64_PLACEHOLDER: u64 = u32::MAX as u64;
+
+// Nothing the game ships comes anywhere near this. Anything that claims
+// to be bigger is much more likely broken (or a zip bomb) than real.
+pub const DEFAULT_MAX_ENTRY_SIZE: u64 = 1 << 30;
+// How much `unpack` allocates up front, past that the buffer grows as the
+// data comes in.
+const UNPACK_PREALLOCATE_LIMIT: u64 = 1 << 20;
+
+pub struct PakEntry {
+    pub name: String,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub crc32: u32,
+    pub modified: DosDateTime,
+    // where the local header starts; the data follows it
+    header_offset: u64,
+    // where the data ends, at the earliest
+    min_data_end: u64,
+    compression: Compression,
+}
+
+// Zip timestamps are in local time with a two second resolution, and
+// there's no timezone, so we don't pretend it's anything more than this.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DosDateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+impl DosDateTime {
+    fn from_dos(date: u16, time: u16) -> Self {
+        Self {
+            year: 1980 + (date >> 9),
+            month: ((date >> 5) & 0xf) as u8,
+            day: (date & 0x1f) as u8,
+            hour: (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3f) as u8,
+            second: ((time & 0x1f) * 2) as u8,
+        }
+    }
+    // (date, time), the way they're stored
+    pub(crate) fn to_dos(self) -> (u16, u16) {
+        let date = (self.year.saturating_sub(1980) << 9)
+            | ((self.month as u16 & 0xf) << 5)
+            | (self.day as u16 & 0x1f);
+        let time = ((self.hour as u16) << 11)
+            | ((self.minute as u16 & 0x3f) << 5)
+            | ((self.second as u16 / 2) & 0x1f);
+        (date, time)
+    }
+}
+impl std::fmt::Display for DosDateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
 }
 pub struct PakArchive<T> {
     source: T,
-    index: Vec<IndexEntry>,
+    // only used for error reporting
+    path: PathBuf,
+    index: Vec<PakEntry>,
+    // normalized name -> positions in `index`
+    lookup: HashMap<String, Vec<usize>>,
+    // no entry data goes past this
+    data_limit: u64,
+    max_entry_size: u64,
+}
+impl PakArchive<std::io::BufReader<std::fs::File>> {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PakError> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|e| PakError::from_io(path, None, e))?;
+        Self::with_path(std::io::BufReader::new(file), path)
+    }
 }
 impl<T> PakArchive<T>
 where
-    T: std::io::Read + std::io::Seek,
+    T: Read + Seek,
 {
-    pub fn new(mut source: T) -> std::io::Result<Self> {
+    pub fn with_path(source: T, path: impl Into<PathBuf>) -> Result<Self, PakError> {
+        Self::with_max_entry_size(source, path, DEFAULT_MAX_ENTRY_SIZE)
+    }
+    // Entries that unpack to more than `max_entry_size` are refused outright.
+    pub fn with_max_entry_size(
+        mut source: T,
+        path: impl Into<PathBuf>,
+        max_entry_size: u64,
+    ) -> Result<Self, PakError> {
+        let path = path.into();
+        let (index, data_limit) =
+            read_index(&mut source).map_err(|e| PakError::from_io(&path, None, e))?;
+        let mut lookup: HashMap<String, Vec<usize>> = HashMap::with_capacity(index.len());
+        for (i, entry) in index.iter().enumerate() {
+            lookup
+                .entry(normalize_name(&entry.name))
+                .or_default()
+                .push(i);
+        }
         Ok(Self {
-            index: read_index(&mut source)?,
+            index,
+            lookup,
+            data_limit,
+            max_entry_size,
             source,
+            path,
         })
     }
-    pub fn file_list(&self) -> Vec<String> {
-        self.index.iter().map(|i| i.filename.to_string()).collect()
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn entries(&self) -> &[PakEntry] {
+        &self.index
+    }
+    pub fn entry(&self, filename: &str) -> Result<&PakEntry, PakError> {
+        Ok(&self.index[self.find(filename)?])
     }
-    pub fn unpack(&mut self, filename: &str) -> Vec<u8> {
-        let index_entry = self
-            .index
+    // Case and `/` vs `\` don't matter, same as for `entry` and `open`.
+    pub fn entries_under(&self, prefix: &str) -> impl Iterator<Item = &PakEntry> {
+        let prefix = normalize_name(prefix);
+        self.index
             .iter()
-            .find(|i| i.filename == filename)
-            .ok_or_else(|| format!("File {:?} not present in archive", filename))
-            .unwrap();
-        self.source
-            .seek(std::io::SeekFrom::Start(index_entry.data_offset))
-            .unwrap();
-        let mut compressed_data = vec![0; index_entry.data_len as usize];
-        self.source.read_exact(&mut compressed_data).unwrap();
-        let mut decoder = libflate::deflate::Decoder::new(&compressed_data[..]);
-        let mut decoded_data = vec![];
-        decoder.read_to_end(&mut decoded_data).unwrap();
-        decoded_data
-    }
-}
-
-fn read_index<T>(mut source: T) -> std::io::Result<Vec<IndexEntry>>
+            .filter(move |i| normalize_name(&i.name).starts_with(&prefix))
+    }
+    // Groups of entries that only differ in case or separators. These can
+    // only be looked up by their exact name.
+    pub fn ambiguous_names(&self) -> Vec<Vec<&str>> {
+        let mut result: Vec<Vec<&str>> = self
+            .lookup
+            .values()
+            .filter(|positions| positions.len() > 1)
+            .map(|positions| {
+                positions
+                    .iter()
+                    .map(|&i| self.index[i].name.as_str())
+                    .collect()
+            })
+            .collect();
+        result.sort();
+        result
+    }
+    fn find(&self, filename: &str) -> Result<usize, PakError> {
+        let positions =
+            self.lookup
+                .get(&normalize_name(filename))
+                .ok_or_else(|| PakError::MissingEntry {
+                    archive: self.path.clone(),
+                    entry: filename.to_string(),
+                })?;
+        if let [i] = positions.as_slice() {
+            return Ok(*i);
+        }
+        // several entries normalize to the same thing, the exact spelling wins
+        let exact: Vec<usize> = positions
+            .iter()
+            .copied()
+            .filter(|&i| self.index[i].name == filename)
+            .collect();
+        if let [i] = exact.as_slice() {
+            return Ok(*i);
+        }
+        Err(PakError::Ambiguous {
+            archive: self.path.clone(),
+            entry: filename.to_string(),
+            candidates: positions
+                .iter()
+                .map(|&i| self.index[i].name.clone())
+                .collect(),
+        })
+    }
+    // See `glob_match` for what the pattern can do.
+    pub fn glob<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = &'a PakEntry> {
+        self.index
+            .iter()
+            .filter(move |i| glob_match(pattern, &i.name))
+    }
+    pub fn unpack(&mut self, filename: &str) -> Result<Vec<u8>, PakError> {
+        let mut reader = self.open(filename)?;
+        // the size is only what the header claims until the data is read
+        let capacity = std::cmp::min(reader.uncompressed_size(), UNPACK_PREALLOCATE_LIMIT);
+        let mut data = Vec::with_capacity(capacity as usize);
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| reader.pak_error(e))?;
+        Ok(data)
+    }
+    // Streams the decompressed bytes straight out of the source. The size and
+    // crc are checked once the reader hits the end of the entry.
+    pub fn open(&mut self, filename: &str) -> Result<EntryReader<'_, T>, PakError> {
+        let position = self.find(filename)?;
+        let path = &self.path;
+        let source = &mut self.source;
+        let index_entry = &self.index[position];
+        let filename = index_entry.name.as_str();
+        if let Compression::Unsupported(method) = index_entry.compression {
+            return Err(PakError::Decompress {
+                archive: path.clone(),
+                entry: filename.to_string(),
+                source: std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("unsupported compression method {}", method),
+                ),
+            });
+        }
+        if index_entry.uncompressed_size > self.max_entry_size {
+            return Err(PakError::TooLarge {
+                archive: path.clone(),
+                entry: filename.to_string(),
+                size: index_entry.uncompressed_size,
+                limit: self.max_entry_size,
+            });
+        }
+        let data_limit = self.data_limit;
+        data_offset(&mut *source, index_entry.header_offset)
+            .and_then(|offset| {
+                if offset.saturating_add(index_entry.compressed_size) > data_limit {
+                    return Err(malformed("data runs past the end of the archive".into()));
+                }
+                source.seek(SeekFrom::Start(offset))
+            })
+            .map_err(|e| PakError::from_io(path, Some(filename), e))?;
+        let raw = source.take(index_entry.compressed_size);
+        let data = match index_entry.compression {
+            Compression::Stored => EntryData::Stored(raw),
+            Compression::Deflate => EntryData::Deflate(libflate::deflate::Decoder::new(raw)),
+            Compression::Unsupported(_) => unreachable!(),
+        };
+        Ok(EntryReader {
+            data,
+            archive: path,
+            entry: filename.to_string(),
+            expected_size: index_entry.uncompressed_size,
+            expected_crc: index_entry.crc32,
+            hasher: crc32fast::Hasher::new(),
+            size: 0,
+        })
+    }
+}
+
+enum EntryData<'a, T> {
+    Stored(std::io::Take<&'a mut T>),
+    Deflate(libflate::deflate::Decoder<std::io::Take<&'a mut T>>),
+}
+
+pub struct EntryReader<'a, T> {
+    data: EntryData<'a, T>,
+    archive: &'a Path,
+    entry: String,
+    expected_size: u64,
+    expected_crc: u32,
+    hasher: crc32fast::Hasher,
+    // how much we've handed out so far
+    size: u64,
+}
+impl<'a, T> EntryReader<'a, T> {
+    pub fn uncompressed_size(&self) -> u64 {
+        self.expected_size
+    }
+    fn error(&self, err: PakError) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+    // Gets back the `PakError` that `read` tucked into an io error, or makes
+    // one if it came from elsewhere.
+    fn pak_error(&self, err: std::io::Error) -> PakError {
+        if matches!(err.get_ref(), Some(e) if e.is::<PakError>()) {
+            return *err.into_inner().unwrap().downcast::<PakError>().unwrap();
+        }
+        PakError::from_io(self.archive, Some(&self.entry), err)
+    }
+    fn check(&self) -> Result<(), PakError> {
+        // half-patched installs have been known to have entries cut short
+        if self.size != self.expected_size {
+            return Err(PakError::SizeMismatch {
+                archive: self.archive.to_path_buf(),
+                entry: self.entry.clone(),
+                expected: self.expected_size,
+                actual: self.size,
+            });
+        }
+        let crc = self.hasher.clone().finalize();
+        if crc != self.expected_crc {
+            return Err(PakError::CrcMismatch {
+                archive: self.archive.to_path_buf(),
+                entry: self.entry.clone(),
+                expected: self.expected_crc,
+                actual: crc,
+            });
+        }
+        Ok(())
+    }
+}
+impl<'a, T> Read for EntryReader<'a, T>
 where
-    T: std::io::Read + std::io::Seek,
+    T: Read,
 {
-    // let mut rdr = Reader::new(source);
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = match &mut self.data {
+            EntryData::Stored(rdr) => rdr
+                .read(buf)
+                .map_err(|e| PakError::from_io(self.archive, Some(&self.entry), e)),
+            EntryData::Deflate(rdr) => rdr.read(buf).map_err(|e| PakError::Decompress {
+                archive: self.archive.to_path_buf(),
+                entry: self.entry.clone(),
+                source: e,
+            }),
+        };
+        let amt = read.map_err(|e| self.error(e))?;
+        self.hasher.update(&buf[..amt]);
+        self.size += amt as u64;
+        if self.size > self.expected_size || (amt == 0 && !buf.is_empty()) {
+            self.check().map_err(|e| self.error(e))?;
+        }
+        Ok(amt)
+    }
+}
+
+// The local header repeats the filename and may carry a different extra field
+// than the central directory does, so the data offset has to come from it.
+fn data_offset<T>(mut source: T, header_offset: u64) -> std::io::Result<u64>
+where
+    T: Read + Seek,
+{
+    source.seek(SeekFrom::Start(header_offset))?;
+    let magic = Reader2::read_bytes(&mut source, 4)?;
+    check_magic(magic, LOCAL_HEADER_MAGIC, header_offset)?;
+    source.seek(SeekFrom::Start(header_offset + 26))?;
+    let filename_len = Reader2::read_u16(&mut source)?;
+    let extra_field_len = Reader2::read_u16(&mut source)?;
+    Ok(header_offset + LOCAL_HEADER_LEN + filename_len as u64 + extra_field_len as u64)
+}
+
+// Also gives back how far into the stream entry data may go.
+fn read_index<T>(mut source: T) -> std::io::Result<(Vec<PakEntry>, u64)>
+where
+    T: Read + Seek,
+{
+    match find_end_of_central_dir(&mut source)? {
+        Some(eocd) => {
+            let index = read_central_dir(&mut source, &eocd)?;
+            check_layout(&index, eocd.dir_offset)?;
+            Ok((index, eocd.dir_offset))
+        }
+        None => {
+            // no end record - the archive is most likely cut short.
+            // salvage whatever complete entries are still there
+            let stream_len = source.seek(SeekFrom::End(0))?;
+            Ok((walk_local_headers(&mut source)?, stream_len))
+        }
+    }
+}
+
+// Every entry has to sit before the central directory and no two entries
+// can share bytes. Overlapping entries are how the nastier zip bombs work.
+fn check_layout(index: &[PakEntry], dir_offset: u64) -> std::io::Result<()> {
+    let mut by_offset: Vec<&PakEntry> = index.iter().collect();
+    by_offset.sort_by_key(|e| e.header_offset);
+    for entry in &by_offset {
+        if entry.min_data_end > dir_offset {
+            return Err(malformed(format!(
+                "{:?} runs into the central directory",
+                entry.name
+            )));
+        }
+    }
+    for pair in by_offset.windows(2) {
+        if pair[1].header_offset < pair[0].min_data_end {
+            return Err(malformed(format!(
+                "{:?} and {:?} overlap",
+                pair[0].name, pair[1].name
+            )));
+        }
+    }
+    Ok(())
+}
+
+struct EndOfCentralDir {
+    entry_count: u64,
+    // where the central directory actually is in the stream
+    dir_offset: u64,
+    dir_size: u64,
+    // added to every offset stored in the archive, for when something
+    // has been prepended to it
+    base_offset: u64,
+}
+
+fn find_end_of_central_dir<T>(mut source: T) -> std::io::Result<Option<EndOfCentralDir>>
+where
+    T: Read + Seek,
+{
+    let stream_len = source.seek(SeekFrom::End(0))?;
+    // the record is at the very end, followed by at most 64k of comment
+    let tail_len = std::cmp::min(stream_len, END_OF_CENTRAL_DIR_LEN + u16::MAX as u64);
+    let tail_start = stream_len - tail_len;
+    source.seek(SeekFrom::Start(tail_start))?;
+    let tail = Reader2::read_bytes(&mut source, tail_len as usize)?;
+
+    let record_len = END_OF_CENTRAL_DIR_LEN as usize;
+    if tail.len() < record_len {
+        return Ok(None);
+    }
+    for pos in (0..=tail.len() - record_len).rev() {
+        if &tail[pos..pos + 4] != END_OF_CENTRAL_DIR_MAGIC {
+            continue;
+        }
+        let mut rdr = &tail[pos + 4..];
+        let _disk = Reader2::read_u16(&mut rdr)?;
+        let _dir_disk = Reader2::read_u16(&mut rdr)?;
+        let _entries_on_disk = Reader2::read_u16(&mut rdr)?;
+        let entry_count = Reader2::read_u16(&mut rdr)?;
+        let dir_size = Reader2::read_u32(&mut rdr)? as u64;
+        let stored_dir_offset = Reader2::read_u32(&mut rdr)? as u64;
+        let comment_len = Reader2::read_u16(&mut rdr)? as usize;
+        if pos + record_len + comment_len > tail.len() {
+            // just some bytes that happen to look like the magic
+            continue;
+        }
+
+        let record_offset = tail_start + pos as u64;
+        if let Some(eocd) = find_zip64_end_of_central_dir(&mut source, record_offset)? {
+            return Ok(Some(eocd));
+        }
+        if record_offset < dir_size + stored_dir_offset {
+            // directory claims to be past the end record
+            continue;
+        }
+        let dir_offset = record_offset - dir_size;
+        return Ok(Some(EndOfCentralDir {
+            entry_count: entry_count as u64,
+            dir_offset,
+            dir_size,
+            base_offset: dir_offset - stored_dir_offset,
+        }));
+    }
+    Ok(None)
+}
+
+// Archives over 4 GiB keep the real directory size and offset in a second,
+// 64-bit end record. A locator right before the regular end record says
+// where that one is.
+fn find_zip64_end_of_central_dir<T>(
+    mut source: T,
+    record_offset: u64,
+) -> std::io::Result<Option<EndOfCentralDir>>
+where
+    T: Read + Seek,
+{
+    if record_offset < ZIP64_LOCATOR_LEN {
+        return Ok(None);
+    }
+    let locator_offset = record_offset - ZIP64_LOCATOR_LEN;
+    source.seek(SeekFrom::Start(locator_offset))?;
+    let locator = Reader2::read_bytes(&mut source, ZIP64_LOCATOR_LEN as usize)?;
+    let mut rdr = &locator[..];
+    if Reader2::read_bytes(&mut rdr, 4)? != ZIP64_LOCATOR_MAGIC {
+        return Ok(None);
+    }
+    let _dir_disk = Reader2::read_u32(&mut rdr)?;
+    let stored_record_offset = Reader2::read_u64(&mut rdr)?;
+
+    // if something got prepended to the archive the stored offset is off,
+    // but the record is normally right before the locator anyway
+    let candidates = [
+        stored_record_offset,
+        locator_offset.saturating_sub(ZIP64_END_OF_CENTRAL_DIR_LEN),
+    ];
+    for &record_offset in candidates.iter() {
+        if record_offset.saturating_add(ZIP64_END_OF_CENTRAL_DIR_LEN) > locator_offset {
+            continue;
+        }
+        source.seek(SeekFrom::Start(record_offset))?;
+        let record = Reader2::read_bytes(&mut source, ZIP64_END_OF_CENTRAL_DIR_LEN as usize)?;
+        let mut rdr = &record[..];
+        if Reader2::read_bytes(&mut rdr, 4)? != ZIP64_END_OF_CENTRAL_DIR_MAGIC {
+            continue;
+        }
+        let _record_len = Reader2::read_u64(&mut rdr)?;
+        let _ver_made_by = Reader2::read_u16(&mut rdr)?;
+        let _ver_needed = Reader2::read_u16(&mut rdr)?;
+        let _disk = Reader2::read_u32(&mut rdr)?;
+        let _dir_disk = Reader2::read_u32(&mut rdr)?;
+        let _entries_on_disk = Reader2::read_u64(&mut rdr)?;
+        let entry_count = Reader2::read_u64(&mut rdr)?;
+        let dir_size = Reader2::read_u64(&mut rdr)?;
+        let stored_dir_offset = Reader2::read_u64(&mut rdr)?;
+
+        let base_offset = record_offset - stored_record_offset.min(record_offset);
+        let dir_offset = base_offset.saturating_add(stored_dir_offset);
+        if dir_offset.saturating_add(dir_size) > record_offset {
+            return Err(malformed(
+                "central directory runs past the zip64 end record".into(),
+            ));
+        }
+        return Ok(Some(EndOfCentralDir {
+            entry_count,
+            dir_offset,
+            dir_size,
+            base_offset,
+        }));
+    }
+    Err(malformed("zip64 end record is missing".into()))
+}
+
+fn read_central_dir<T>(mut source: T, eocd: &EndOfCentralDir) -> std::io::Result<Vec<PakEntry>>
+where
+    T: Read + Seek,
+{
+    source.seek(SeekFrom::Start(eocd.dir_offset))?;
+    let dir = Reader2::read_bytes(&mut source, eocd.dir_size as usize)?;
+    let mut rdr = &dir[..];
+
+    // don't trust the count further than the directory size can back it up
+    let max_entries = eocd.dir_size / CENTRAL_HEADER_LEN;
+    let mut index = Vec::with_capacity(std::cmp::min(eocd.entry_count, max_entries) as usize);
+    while !rdr.is_empty() {
+        let entry_offset = eocd.dir_offset + (dir.len() - rdr.len()) as u64;
+        let magic = Reader2::read_bytes(&mut rdr, 4)?;
+        check_magic(magic, CENTRAL_HEADER_MAGIC, entry_offset)?;
+        let _ver_made_by = Reader2::read_u16(&mut rdr)?;
+        let _ver_needed = Reader2::read_u16(&mut rdr)?;
+        let _opts = Reader2::read_u16(&mut rdr)?;
+        let compression_method = Reader2::read_u16(&mut rdr)?;
+        let last_modified_time = Reader2::read_u16(&mut rdr)?;
+        let last_modified_date = Reader2::read_u16(&mut rdr)?;
+        let crc = Reader2::read_u32(&mut rdr)?;
+        let mut compressed_size = Reader2::read_u32(&mut rdr)? as u64;
+        let mut uncompressed_size = Reader2::read_u32(&mut rdr)? as u64;
+        let filename_len = Reader2::read_u16(&mut rdr)?;
+        let extra_field_len = Reader2::read_u16(&mut rdr)?;
+        let comment_len = Reader2::read_u16(&mut rdr)?;
+        let _disk = Reader2::read_u16(&mut rdr)?;
+        let _internal_attrs = Reader2::read_u16(&mut rdr)?;
+        let _external_attrs = Reader2::read_u32(&mut rdr)?;
+        let mut header_offset = Reader2::read_u32(&mut rdr)? as u64;
+        let filename =
+            String::from_utf8_lossy(&Reader2::read_bytes(&mut rdr, filename_len as usize)?)
+                .into_owned();
+        let extra_field = Reader2::read_bytes(&mut rdr, extra_field_len as usize)?;
+        let _comment = Reader2::read_bytes(&mut rdr, comment_len as usize)?;
+        apply_zip64_extra(
+            &extra_field,
+            &filename,
+            &mut [
+                &mut uncompressed_size,
+                &mut compressed_size,
+                &mut header_offset,
+            ],
+        )?;
+
+        let header_offset = eocd.base_offset.saturating_add(header_offset);
+        index.push(PakEntry {
+            name: filename,
+            header_offset,
+            min_data_end: header_offset
+                .saturating_add(LOCAL_HEADER_LEN + filename_len as u64)
+                .saturating_add(compressed_size),
+            compressed_size,
+            uncompressed_size,
+            crc32: crc,
+            modified: DosDateTime::from_dos(last_modified_date, last_modified_time),
+            compression: Compression::from_method(compression_method),
+        });
+    }
+
+    Ok(index)
+}
+
+fn walk_local_headers<T>(mut source: T) -> std::io::Result<Vec<PakEntry>>
+where
+    T: Read + Seek,
+{
+    let stream_len = source.seek(SeekFrom::End(0))?;
+    source.seek(SeekFrom::Start(0))?;
 
     let mut index = Vec::new();
 
     loop {
+        let header_offset = source.stream_position()?;
+        if header_offset + LOCAL_HEADER_LEN > stream_len {
+            break;
+        }
         let magic = Reader2::read_bytes(&mut source, 4)?;
         match magic.as_slice() {
-            b"PK\x03\x04" => { /*is a file*/ }
-            b"PK\x01\x02" => {
-                // is a central directory
-                // we could parse that but nah, let's just break out
+            LOCAL_HEADER_MAGIC => { /*is a file*/ }
+            CENTRAL_HEADER_MAGIC => {
+                // is a central directory, so all the files are behind us
                 break;
             }
-            _ => {
-                panic!("idk magic {:?}", magic)
-            }
+            _ => check_magic(magic, LOCAL_HEADER_MAGIC, header_offset)?,
         }
-        assert_eq!(magic.as_slice(), b"PK\x03\x04");
         let _ver = Reader2::read_bytes(&mut source, 2)?;
-        let _opts = Reader2::read_bytes(&mut source, 2)?;
+        let opts = Reader2::read_u16(&mut source)?;
         let compression_method = Reader2::read_u16(&mut source)?;
-        assert_eq!(compression_method, 8); // deflate
-        let _last_modified_time = Reader2::read_u16(&mut source)?;
-        let _last_modified_date = Reader2::read_u16(&mut source)?;
-
-        let _crc = Reader2::read_bytes(&mut source, 4)?;
-        let compressed_size = Reader2::read_u32(&mut source)?;
-        let _uncompressed_size = Reader2::read_u32(&mut source)?;
-        // println!("sizes {} {}", compressed_size, uncompressed_size);
+        let last_modified_time = Reader2::read_u16(&mut source)?;
+        let last_modified_date = Reader2::read_u16(&mut source)?;
+
+        let crc = Reader2::read_u32(&mut source)?;
+        let mut compressed_size = Reader2::read_u32(&mut source)? as u64;
+        let mut uncompressed_size = Reader2::read_u32(&mut source)? as u64;
+        if opts & FLAG_DATA_DESCRIPTOR != 0 {
+            // sizes and crc come after the data, so there's no telling
+            // where this entry ends without inflating it
+            break;
+        }
         let filename_len = Reader2::read_u16(&mut source)?;
         let extra_field_len = Reader2::read_u16(&mut source)?;
-        // println!("filenames {} {}", filename_len, extra_field_len);
+        let data_offset =
+            header_offset + LOCAL_HEADER_LEN + filename_len as u64 + extra_field_len as u64;
+        if data_offset > stream_len {
+            break;
+        }
         let filename =
-            String::from_utf8(Reader2::read_bytes(&mut source, filename_len as usize)?).unwrap();
-        let _extra_field = Reader2::read_bytes(&mut source, extra_field_len as usize)?;
+            String::from_utf8_lossy(&Reader2::read_bytes(&mut source, filename_len as usize)?)
+                .into_owned();
+        let extra_field = Reader2::read_bytes(&mut source, extra_field_len as usize)?;
+        apply_zip64_extra(
+            &extra_field,
+            &filename,
+            &mut [&mut uncompressed_size, &mut compressed_size],
+        )?;
+        if data_offset.saturating_add(compressed_size) > stream_len {
+            // this is where the archive got cut off
+            break;
+        }
 
-        index.push(IndexEntry {
-            filename,
-            data_offset: std::io::Seek::seek(&mut source, std::io::SeekFrom::Current(0))?,
-            data_len: compressed_size as u64,
+        index.push(PakEntry {
+            name: filename,
+            header_offset,
+            min_data_end: data_offset + compressed_size,
+            compressed_size,
+            uncompressed_size,
+            crc32: crc,
+            modified: DosDateTime::from_dos(last_modified_date, last_modified_time),
+            compression: Compression::from_method(compression_method),
         });
-        std::io::Seek::seek(
-            &mut source,
-            std::io::SeekFrom::Current(compressed_size as i64),
-        )?;
+        source.seek(SeekFrom::Start(data_offset + compressed_size))?;
     }
 
     Ok(index)
 }
 
+// Fields that didn't fit in 32 bits hold a placeholder, and the real values
+// are in the zip64 extra field - only the ones that are needed, in the order
+// `fields` lists them.
+fn apply_zip64_extra(mut extra: &[u8], name: &str, fields: &mut [&mut u64]) -> std::io::Result<()> {
+    if fields.iter().all(|f| **f != ZIP64_PLACEHOLDER) {
+        return Ok(());
+    }
+    while extra.len() >= 4 {
+        let id = Reader2::read_u16(&mut extra)?;
+        let len = Reader2::read_u16(&mut extra)? as usize;
+        if extra.len() < len {
+            break;
+        }
+        let (mut data, rest) = extra.split_at(len);
+        extra = rest;
+        if id != ZIP64_EXTRA_ID {
+            continue;
+        }
+        for field in fields.iter_mut().filter(|f| ***f == ZIP64_PLACEHOLDER) {
+            **field = Reader2::read_u64(&mut data)
+                .map_err(|_| malformed(format!("{:?} has a short zip64 extra field", name)))?;
+        }
+        return Ok(());
+    }
+    Err(malformed(format!(
+        "{:?} is missing its zip64 extra field",
+        name
+    )))
+}
+
 // struct Reader<R> {
 //     rdr: R,
 // }
@@ -119,6 +859,54 @@ where
 //     }
 // }
 
+// The game isn't consistent about either case or which slash it uses.
+pub fn normalize_name(name: &str) -> String {
+    name.replace('\\', "/")
+        .trim_start_matches('/')
+        .to_lowercase()
+}
+
+// `*` and `?` stay within one path component, `**` goes across them.
+// A pattern without any `/` is matched against just the file name, so
+// `*.mdf` finds mdfs anywhere in the archive. Ignores case and slash
+// direction, like the lookups do.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern = normalize_name(pattern);
+    let path = normalize_name(path);
+    let path = if pattern.contains('/') {
+        path.as_str()
+    } else {
+        path.rsplit('/').next().unwrap_or(&path)
+    };
+    wildcard_match(pattern.as_bytes(), path.as_bytes())
+}
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            // `a/**/b` should also match `a/b`
+            if let [b'/', after_slash @ ..] = rest {
+                if wildcard_match(after_slash, name) {
+                    return true;
+                }
+            }
+            (0..=name.len()).any(|i| wildcard_match(rest, &name[i..]))
+        }
+        [b'*', rest @ ..] => {
+            let component_end = name.iter().position(|&c| c == b'/').unwrap_or(name.len());
+            (0..=component_end).any(|i| wildcard_match(rest, &name[i..]))
+        }
+        [b'?', rest @ ..] => match name {
+            [c, tail @ ..] => *c != b'/' && wildcard_match(rest, tail),
+            [] => false,
+        },
+        [p, rest @ ..] => match name {
+            [c, tail @ ..] => c == p && wildcard_match(rest, tail),
+            [] => false,
+        },
+    }
+}
+
 struct Reader2;
 impl Reader2 {
     fn read_bytes<R: std::io::Read>(mut rdr: R, count: usize) -> std::io::Result<Vec<u8>> {
@@ -136,4 +924,9 @@ impl Reader2 {
         rdr.read_exact(&mut buf)?;
         Ok(u32::from_le_bytes(buf))
     }
+    fn read_u64<R: std::io::Read>(mut rdr: R) -> std::io::Result<u64> {
+        let mut buf = [0u8; 8];
+        rdr.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
 }
@@ -0,0 +1,119 @@
+// What changed between two versions of a pak, or of a whole install.
+
+use crate::game_fs::GameFs;
+use crate::pak_archive::{normalize_name, PakArchive, PakEntry, PakError};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+pub enum Tree {
+    Pak(PakArchive<std::io::BufReader<std::fs::File>>),
+    Install(GameFs),
+}
+
+impl Tree {
+    // A directory is taken as an install (either the install itself or its
+    // Game/ folder), anything else as a single pak.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PakError> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Ok(Tree::Pak(PakArchive::from_file(path)?));
+        }
+        let game_dir = path.join("Game");
+        let mut game_fs = if game_dir.is_dir() {
+            GameFs::mount(game_dir)?
+        } else {
+            GameFs::mount(path)?
+        };
+        // a diff reads every file at most once
+        game_fs.set_cache_limit(0);
+        Ok(Tree::Install(game_fs))
+    }
+
+    pub fn read(&mut self, name: &str) -> Result<Rc<[u8]>, PakError> {
+        match self {
+            Tree::Pak(archive) => Ok(archive.unpack(name)?.into()),
+            Tree::Install(game_fs) => game_fs.read(name),
+        }
+    }
+
+    // normalized name -> entry
+    fn entries(&self) -> BTreeMap<String, &PakEntry> {
+        let entries = match self {
+            Tree::Pak(archive) => archive.entries().iter().collect(),
+            Tree::Install(game_fs) => game_fs.entries(),
+        };
+        entries
+            .into_iter()
+            .map(|e| (normalize_name(&e.name), e))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct EntryChange {
+    // as spelled in the new tree, or the old one for removed entries
+    pub name: String,
+    pub change: Change,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+    pub old_crc32: Option<u32>,
+    pub new_crc32: Option<u32>,
+}
+
+// Entries are matched up by name, ignoring case and slash direction. An entry
+// counts as modified when its CRC or size changed. Sorted by name.
+pub fn diff(old: &Tree, new: &Tree) -> Vec<EntryChange> {
+    let old_entries = old.entries();
+    let new_entries = new.entries();
+    let mut keys: Vec<&String> = old_entries.keys().chain(new_entries.keys()).collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let mut result = vec![];
+    for key in keys {
+        let old_entry = old_entries.get(key);
+        let new_entry = new_entries.get(key);
+        let (name, change) = match (old_entry, new_entry) {
+            (None, Some(n)) => (&n.name, Change::Added),
+            (Some(o), None) => (&o.name, Change::Removed),
+            (Some(o), Some(n)) => {
+                if o.crc32 == n.crc32 && o.uncompressed_size == n.uncompressed_size {
+                    continue;
+                }
+                (&n.name, Change::Modified)
+            }
+            (None, None) => unreachable!(),
+        };
+        result.push(EntryChange {
+            name: name.clone(),
+            change,
+            old_size: old_entry.map(|e| e.uncompressed_size),
+            new_size: new_entry.map(|e| e.uncompressed_size),
+            old_crc32: old_entry.map(|e| e.crc32),
+            new_crc32: new_entry.map(|e| e.crc32),
+        });
+    }
+    result
+}
+
+// Line-based unified diff, for the text (xml, mdf) entries. None if either
+// side isn't utf-8, since then it's not really text.
+pub fn unified_text_diff(name: &str, old: &[u8], new: &[u8]) -> Option<String> {
+    let old = std::str::from_utf8(old).ok()?;
+    let new = std::str::from_utf8(new).ok()?;
+    let diff = similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string();
+    Some(diff)
+}
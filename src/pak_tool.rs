@@ -0,0 +1,342 @@
+// `reparse pak ...` - poking around the game archives without an unzipper.
+
+use crate::pak_archive::{self, PakArchive, PakEntry};
+use crate::pak_diff::{self, Change, Tree};
+use crate::pak_writer::{Method, PakWriter};
+use anyhow::{anyhow, bail, Context};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+const USAGE: &str = "usage:
+    reparse pak list [--json] [--under <prefix>] <pak>...
+    reparse pak cat <pak> <entry>
+    reparse pak extract [--json] [--glob <pattern>] <pak> <dest>
+    reparse pak grep [--json] [--glob <pattern>] <regex> <pak>...
+    reparse pak pack [--stored] <pak> <dir>
+    reparse pak diff [--json] [--text] <old pak or install> <new pak or install>";
+
+// what `grep` looks through unless told otherwise
+const XML_GLOBS: &[&str] = &["*.xml", "*.mdf"];
+
+pub fn main(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.to_vec();
+    let json = take_flag(&mut args, "--json");
+    let stored = take_flag(&mut args, "--stored");
+    let text = take_flag(&mut args, "--text");
+    let glob = take_option(&mut args, "--glob")?;
+    let under = take_option(&mut args, "--under")?;
+    let (command, rest) = args.split_first().ok_or_else(|| anyhow!("{}", USAGE))?;
+    let result = match (command.as_str(), rest) {
+        ("list", paks) if !paks.is_empty() => list(paks, under.as_deref().unwrap_or(""), json),
+        ("cat", [pak, entry]) => cat(pak, entry),
+        ("extract", [pak, dest]) => extract(pak, dest, glob.as_deref(), json),
+        ("grep", [pattern, paks @ ..]) if !paks.is_empty() => {
+            grep(pattern, paks, glob.as_deref(), json)
+        }
+        ("pack", [pak, dir]) => pack(pak, dir, stored),
+        ("diff", [old, new]) => diff(old, new, text, json),
+        _ => bail!("{}", USAGE),
+    };
+    match result {
+        // whatever reads the output, like `head`, has seen enough
+        Err(e) if is_broken_pipe(&e) => Ok(()),
+        other => other,
+    }
+}
+
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(|e| e.kind() == std::io::ErrorKind::BrokenPipe)
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|a| a != flag);
+    args.len() != before
+}
+
+fn take_option(args: &mut Vec<String>, option: &str) -> anyhow::Result<Option<String>> {
+    let pos = match args.iter().position(|a| a == option) {
+        Some(pos) => pos,
+        None => return Ok(None),
+    };
+    if pos + 1 >= args.len() {
+        bail!("{} needs a value", option);
+    }
+    let value = args.remove(pos + 1);
+    args.remove(pos);
+    Ok(Some(value))
+}
+
+fn open(pak: &str) -> anyhow::Result<PakArchive<std::io::BufReader<std::fs::File>>> {
+    Ok(PakArchive::from_file(pak)?)
+}
+
+fn entry_json(pak: &str, entry: &PakEntry) -> serde_json::Value {
+    serde_json::json!({
+        "pak": pak,
+        "name": entry.name,
+        "compressed_size": entry.compressed_size,
+        "uncompressed_size": entry.uncompressed_size,
+        "crc32": entry.crc32,
+        "modified": entry.modified.to_string(),
+    })
+}
+
+fn list(paks: &[String], under: &str, json: bool) -> anyhow::Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for pak in paks {
+        let archive = open(pak)?;
+        if !json && paks.len() > 1 {
+            writeln!(out, "{}:", pak)?;
+        }
+        for entry in archive.entries_under(under) {
+            if json {
+                writeln!(out, "{}", entry_json(pak, entry))?;
+            } else {
+                writeln!(
+                    out,
+                    "{:>12} {:>12}  {}  {}",
+                    entry.uncompressed_size, entry.compressed_size, entry.modified, entry.name
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cat(pak: &str, entry: &str) -> anyhow::Result<()> {
+    let mut archive = open(pak)?;
+    let mut reader = archive.open(entry)?;
+    let stdout = std::io::stdout();
+    std::io::copy(&mut reader, &mut stdout.lock())?;
+    Ok(())
+}
+
+fn extract(pak: &str, dest: &str, glob: Option<&str>, json: bool) -> anyhow::Result<()> {
+    let mut archive = open(pak)?;
+    let names: Vec<String> = archive
+        .entries()
+        .iter()
+        .filter(|e| match glob {
+            Some(g) => pak_archive::glob_match(g, &e.name),
+            None => true,
+        })
+        .map(|e| e.name.clone())
+        .collect();
+
+    let mut failures = 0;
+    for name in names {
+        let result = extract_one(&mut archive, &name, Path::new(dest));
+        match result {
+            Ok(path) if json => println!(
+                "{}",
+                serde_json::json!({"pak": pak, "name": name, "path": path})
+            ),
+            Ok(path) => println!("{}", path.display()),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        bail!("{} entries could not be extracted", failures);
+    }
+    Ok(())
+}
+
+fn extract_one(
+    archive: &mut PakArchive<std::io::BufReader<std::fs::File>>,
+    name: &str,
+    dest: &Path,
+) -> anyhow::Result<PathBuf> {
+    let path = dest.join(safe_relative_path(name)?);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let mut file =
+        std::fs::File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+    std::io::copy(&mut archive.open(name)?, &mut file)
+        .with_context(|| format!("extracting {:?}", name))?;
+    Ok(path)
+}
+
+// Entry names come from the archive, so they don't get to point outside `dest`.
+fn safe_relative_path(name: &str) -> anyhow::Result<PathBuf> {
+    let mut result = PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match Path::new(part).components().next() {
+            None => {}
+            Some(Component::Normal(p)) => result.push(p),
+            Some(Component::CurDir) => {}
+            Some(_) => bail!("refusing to extract {:?}", name),
+        }
+    }
+    if result.as_os_str().is_empty() {
+        bail!("refusing to extract {:?}", name);
+    }
+    Ok(result)
+}
+
+fn grep(pattern: &str, paks: &[String], glob: Option<&str>, json: bool) -> anyhow::Result<()> {
+    let re = regex::Regex::new(pattern)?;
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for pak in paks {
+        let mut archive = open(pak)?;
+        let names: Vec<String> = archive
+            .entries()
+            .iter()
+            .filter(|e| match glob {
+                Some(g) => pak_archive::glob_match(g, &e.name),
+                None => XML_GLOBS
+                    .iter()
+                    .any(|g| pak_archive::glob_match(g, &e.name)),
+            })
+            .map(|e| e.name.clone())
+            .collect();
+        for name in names {
+            let contents = match archive.unpack(&name) {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            };
+            let contents = String::from_utf8_lossy(&contents);
+            for (line_no, line) in contents.lines().enumerate() {
+                if !re.is_match(line) {
+                    continue;
+                }
+                if json {
+                    let hit = serde_json::json!({
+                        "pak": pak,
+                        "name": name,
+                        "line": line_no + 1,
+                        "text": line,
+                    });
+                    writeln!(out, "{}", hit)?;
+                } else {
+                    writeln!(out, "{}:{}:{}: {}", pak, name, line_no + 1, line.trim())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pack(pak: &str, dir: &str, stored: bool) -> anyhow::Result<()> {
+    let method = if stored {
+        Method::Stored
+    } else {
+        Method::Deflate
+    };
+    let dir = Path::new(dir);
+    let mut files = vec![];
+    find_files(dir, &mut files).with_context(|| format!("reading {}", dir.display()))?;
+    files.sort();
+
+    let mut writer = PakWriter::create(pak).with_context(|| format!("creating {}", pak))?;
+    for path in files {
+        // entry names always use forward slashes, whatever the OS
+        let name = path
+            .strip_prefix(dir)
+            .unwrap()
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        writer
+            .add(&name, &data, method)
+            .with_context(|| format!("adding {:?}", name))?;
+        println!("{}", name);
+    }
+    writer
+        .finish()
+        .with_context(|| format!("writing {}", pak))?;
+    Ok(())
+}
+
+fn find_files(dir: &Path, result: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(&path, result)?;
+        } else {
+            result.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn diff(old: &str, new: &str, text: bool, json: bool) -> anyhow::Result<()> {
+    let mut old_tree = Tree::open(old)?;
+    let mut new_tree = Tree::open(new)?;
+    for tree in [&old_tree, &new_tree].iter() {
+        if let Tree::Install(game_fs) = tree {
+            for err in game_fs.mount_errors() {
+                eprintln!("skipping pak: {}", err);
+            }
+            for conflict in game_fs.name_conflicts() {
+                eprintln!("{}", conflict);
+            }
+        }
+    }
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for change in pak_diff::diff(&old_tree, &new_tree) {
+        let is_text = XML_GLOBS
+            .iter()
+            .any(|g| pak_archive::glob_match(g, &change.name));
+        // a file that can't be diffed is still reported as changed, just
+        // without the lines
+        let text_diff = if text && is_text && change.change == Change::Modified {
+            Some(text_diff(&mut old_tree, &mut new_tree, &change.name))
+        } else {
+            None
+        };
+
+        if json {
+            let mut value = serde_json::to_value(&change)?;
+            match text_diff {
+                Some(Ok(text_diff)) => value["diff"] = text_diff.into(),
+                Some(Err(reason)) => value["diff_error"] = reason.into(),
+                None => {}
+            }
+            writeln!(out, "{}", value)?;
+            continue;
+        }
+        let size = |s: Option<u64>| s.map_or("-".to_string(), |s| s.to_string());
+        let marker = match change.change {
+            Change::Added => "A",
+            Change::Removed => "D",
+            Change::Modified => "M",
+        };
+        writeln!(
+            out,
+            "{} {:>12} {:>12}  {}",
+            marker,
+            size(change.old_size),
+            size(change.new_size),
+            change.name
+        )?;
+        match text_diff {
+            Some(Ok(text_diff)) => write!(out, "{}", text_diff)?,
+            Some(Err(reason)) => writeln!(out, "  (no text diff: {})", reason)?,
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+fn text_diff(old_tree: &mut Tree, new_tree: &mut Tree, name: &str) -> Result<String, String> {
+    let old_data = old_tree.read(name).map_err(|e| e.to_string())?;
+    let new_data = new_tree.read(name).map_err(|e| e.to_string())?;
+    pak_diff::unified_text_diff(name, &old_data, &new_data)
+        .ok_or_else(|| "binary file, not utf-8".to_string())
+}
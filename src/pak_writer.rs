@@ -0,0 +1,433 @@
+// Writes paks the reader in `pak_archive` can read. Handy for making small
+// test installs and for packaging up local data overrides.
+
+use crate::pak_archive::{
+    DosDateTime, CENTRAL_HEADER_MAGIC, END_OF_CENTRAL_DIR_MAGIC, LOCAL_HEADER_MAGIC,
+    ZIP64_END_OF_CENTRAL_DIR_MAGIC, ZIP64_EXTRA_ID, ZIP64_LOCATOR_MAGIC, ZIP64_PLACEHOLDER,
+};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+const FLAG_UTF8_NAME: u16 = 1 << 11;
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Stored,
+    Deflate,
+}
+
+struct WrittenEntry {
+    name: String,
+    method: u16,
+    crc32: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    header_offset: u64,
+    modified: DosDateTime,
+}
+
+pub struct PakWriter<W> {
+    sink: W,
+    // how much has been written so far
+    offset: u64,
+    entries: Vec<WrittenEntry>,
+    names: HashSet<String>,
+    modified: DosDateTime,
+}
+
+impl PakWriter<std::io::BufWriter<std::fs::File>> {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        Ok(Self::new(std::io::BufWriter::new(file)))
+    }
+}
+
+impl<W> PakWriter<W>
+where
+    W: Write,
+{
+    pub fn new(sink: W) -> Self {
+        Self {
+            sink,
+            offset: 0,
+            entries: vec![],
+            names: HashSet::new(),
+            // a fixed date by default, so the same files always make the same pak
+            modified: DosDateTime {
+                year: 1980,
+                month: 1,
+                day: 1,
+                hour: 0,
+                minute: 0,
+                second: 0,
+            },
+        }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8], method: Method) -> std::io::Result<()> {
+        if name.len() > u16::MAX as usize {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{:?} is too long for a pak entry name", name),
+            ));
+        }
+        if !self.names.insert(name.to_string()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{:?} is already in the pak", name),
+            ));
+        }
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(data);
+        let crc32 = hasher.finalize();
+        let deflated;
+        let (method, stored_data) = match method {
+            Method::Stored => (METHOD_STORED, data),
+            Method::Deflate => {
+                let mut encoder = libflate::deflate::Encoder::new(Vec::new());
+                encoder.write_all(data)?;
+                deflated = encoder.finish().into_result()?;
+                (METHOD_DEFLATE, deflated.as_slice())
+            }
+        };
+
+        let entry = WrittenEntry {
+            name: name.to_string(),
+            method,
+            crc32,
+            compressed_size: stored_data.len() as u64,
+            uncompressed_size: data.len() as u64,
+            header_offset: self.offset,
+            modified: self.modified,
+        };
+        let header = local_header(&entry);
+        self.write(&header)?;
+        self.write(stored_data)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    // Writes the central directory. Nothing can be added after this.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        let dir_offset = self.offset;
+        let mut dir = vec![];
+        for entry in &self.entries {
+            dir.extend(central_header(entry));
+        }
+        self.write(&dir)?;
+        let dir_size = dir.len() as u64;
+        let entry_count = self.entries.len() as u64;
+
+        let needs_zip64 = entry_count >= u16::MAX as u64
+            || dir_size >= ZIP64_PLACEHOLDER
+            || dir_offset >= ZIP64_PLACEHOLDER;
+        if needs_zip64 {
+            let record_offset = self.offset;
+            let mut record = vec![];
+            record.extend_from_slice(ZIP64_END_OF_CENTRAL_DIR_MAGIC);
+            // size of the rest of the record
+            record.extend_from_slice(&44u64.to_le_bytes());
+            record.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            record.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&entry_count.to_le_bytes());
+            record.extend_from_slice(&entry_count.to_le_bytes());
+            record.extend_from_slice(&dir_size.to_le_bytes());
+            record.extend_from_slice(&dir_offset.to_le_bytes());
+
+            record.extend_from_slice(ZIP64_LOCATOR_MAGIC);
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&record_offset.to_le_bytes());
+            record.extend_from_slice(&1u32.to_le_bytes());
+            self.write(&record)?;
+        }
+
+        let mut record = vec![];
+        record.extend_from_slice(END_OF_CENTRAL_DIR_MAGIC);
+        record.extend_from_slice(&0u16.to_le_bytes());
+        record.extend_from_slice(&0u16.to_le_bytes());
+        let short_count = std::cmp::min(entry_count, u16::MAX as u64) as u16;
+        record.extend_from_slice(&short_count.to_le_bytes());
+        record.extend_from_slice(&short_count.to_le_bytes());
+        record.extend_from_slice(&clamp_u32(dir_size).to_le_bytes());
+        record.extend_from_slice(&clamp_u32(dir_offset).to_le_bytes());
+        // comment length
+        record.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&record)?;
+
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.sink.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+fn local_header(entry: &WrittenEntry) -> Vec<u8> {
+    // the local header has to carry both sizes in the extra field, if either
+    let zip64 =
+        entry.compressed_size >= ZIP64_PLACEHOLDER || entry.uncompressed_size >= ZIP64_PLACEHOLDER;
+    let mut extra = vec![];
+    if zip64 {
+        extra.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+        extra.extend_from_slice(&16u16.to_le_bytes());
+        extra.extend_from_slice(&entry.uncompressed_size.to_le_bytes());
+        extra.extend_from_slice(&entry.compressed_size.to_le_bytes());
+    }
+    let (date, time) = entry.modified.to_dos();
+
+    let mut header = vec![];
+    header.extend_from_slice(LOCAL_HEADER_MAGIC);
+    header.extend_from_slice(&version_needed(zip64).to_le_bytes());
+    header.extend_from_slice(&flags(&entry.name).to_le_bytes());
+    header.extend_from_slice(&entry.method.to_le_bytes());
+    header.extend_from_slice(&time.to_le_bytes());
+    header.extend_from_slice(&date.to_le_bytes());
+    header.extend_from_slice(&entry.crc32.to_le_bytes());
+    // with the extra field there, both sizes are placeholders, not just the
+    // one that doesn't fit
+    let (compressed_size, uncompressed_size) = if zip64 {
+        (ZIP64_PLACEHOLDER as u32, ZIP64_PLACEHOLDER as u32)
+    } else {
+        (entry.compressed_size as u32, entry.uncompressed_size as u32)
+    };
+    header.extend_from_slice(&compressed_size.to_le_bytes());
+    header.extend_from_slice(&uncompressed_size.to_le_bytes());
+    header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+    header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    header.extend_from_slice(entry.name.as_bytes());
+    header.extend_from_slice(&extra);
+    header
+}
+
+fn central_header(entry: &WrittenEntry) -> Vec<u8> {
+    // only the fields that don't fit go in the extra field, in this order
+    let mut zip64_values = vec![];
+    for &value in [
+        entry.uncompressed_size,
+        entry.compressed_size,
+        entry.header_offset,
+    ]
+    .iter()
+    {
+        if value >= ZIP64_PLACEHOLDER {
+            zip64_values.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    let mut extra = vec![];
+    if !zip64_values.is_empty() {
+        extra.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+        extra.extend_from_slice(&(zip64_values.len() as u16).to_le_bytes());
+        extra.extend_from_slice(&zip64_values);
+    }
+    let version = version_needed(!extra.is_empty());
+    let (date, time) = entry.modified.to_dos();
+
+    let mut header = vec![];
+    header.extend_from_slice(CENTRAL_HEADER_MAGIC);
+    header.extend_from_slice(&version.to_le_bytes());
+    header.extend_from_slice(&version.to_le_bytes());
+    header.extend_from_slice(&flags(&entry.name).to_le_bytes());
+    header.extend_from_slice(&entry.method.to_le_bytes());
+    header.extend_from_slice(&time.to_le_bytes());
+    header.extend_from_slice(&date.to_le_bytes());
+    header.extend_from_slice(&entry.crc32.to_le_bytes());
+    header.extend_from_slice(&clamp_u32(entry.compressed_size).to_le_bytes());
+    header.extend_from_slice(&clamp_u32(entry.uncompressed_size).to_le_bytes());
+    header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+    header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    // comment length, disk, internal and external attributes
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&clamp_u32(entry.header_offset).to_le_bytes());
+    header.extend_from_slice(entry.name.as_bytes());
+    header.extend_from_slice(&extra);
+    header
+}
+
+fn version_needed(zip64: bool) -> u16 {
+    if zip64 {
+        VERSION_ZIP64
+    } else {
+        VERSION_DEFAULT
+    }
+}
+
+fn flags(name: &str) -> u16 {
+    if name.is_ascii() {
+        0
+    } else {
+        FLAG_UTF8_NAME
+    }
+}
+
+// anything too big for 32 bits is written as the zip64 placeholder
+fn clamp_u32(value: u64) -> u32 {
+    std::cmp::min(value, ZIP64_PLACEHOLDER) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pak_archive::{PakArchive, PakError};
+    use std::io::Cursor;
+
+    fn write_pak(files: &[(&str, &[u8], Method)]) -> Vec<u8> {
+        let mut writer = PakWriter::new(vec![]);
+        for (name, data, method) in files {
+            writer.add(name, data, *method).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read_pak(bytes: Vec<u8>) -> PakArchive<Cursor<Vec<u8>>> {
+        PakArchive::with_path(Cursor::new(bytes), "<test>").unwrap()
+    }
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = "Quirk data ".repeat(200);
+        let bytes = write_pak(&[
+            ("Libs/Stored.txt", b"stored as is", Method::Stored),
+            ("Libs/Deflated.xml", text.as_bytes(), Method::Deflate),
+            ("Libs/Empty.xml", b"", Method::Deflate),
+        ]);
+        let mut pak = read_pak(bytes);
+
+        let names: Vec<&str> = pak.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["Libs/Stored.txt", "Libs/Deflated.xml", "Libs/Empty.xml"]
+        );
+        let deflated = pak.entry("Libs/Deflated.xml").unwrap();
+        assert_eq!(deflated.uncompressed_size, text.len() as u64);
+        assert!(deflated.compressed_size < deflated.uncompressed_size);
+        assert_eq!(deflated.crc32, crc32(text.as_bytes()));
+        assert_eq!(deflated.modified.year, 1980);
+
+        assert_eq!(pak.unpack("Libs/Stored.txt").unwrap(), b"stored as is");
+        assert_eq!(pak.unpack("libs\\deflated.xml").unwrap(), text.as_bytes());
+        assert_eq!(pak.unpack("Libs/Empty.xml").unwrap(), b"");
+    }
+
+    #[test]
+    fn crc_mismatch() {
+        let name = "Broken.txt";
+        let mut bytes = write_pak(&[(name, b"some data", Method::Stored)]);
+        // the data of the first entry follows right after its local header
+        bytes[30 + name.len()] ^= 0xff;
+        let mut pak = read_pak(bytes);
+        match pak.unpack(name) {
+            Err(PakError::CrcMismatch {
+                expected, actual, ..
+            }) => {
+                assert_eq!(expected, crc32(b"some data"));
+                assert_ne!(expected, actual);
+            }
+            other => panic!("expected a crc mismatch, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn entry_over_the_limit() {
+        let data = vec![0; 5000];
+        let bytes = write_pak(&[("Big.dat", &data, Method::Deflate)]);
+        let mut pak = PakArchive::with_max_entry_size(Cursor::new(bytes), "<test>", 4096).unwrap();
+        assert!(matches!(
+            pak.unpack("Big.dat"),
+            Err(PakError::TooLarge {
+                size: 5000,
+                limit: 4096,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn prepended_data() {
+        let mut bytes = b"#!/bin/sh\nexec something\n".to_vec();
+        bytes.extend(write_pak(&[
+            ("A.txt", b"first", Method::Stored),
+            ("B.txt", b"second second second", Method::Deflate),
+        ]));
+        let mut pak = read_pak(bytes);
+        assert_eq!(pak.unpack("A.txt").unwrap(), b"first");
+        assert_eq!(pak.unpack("B.txt").unwrap(), b"second second second");
+    }
+
+    #[test]
+    fn truncated_archive_falls_back_to_local_headers() {
+        let bytes = write_pak(&[
+            ("A.txt", b"first", Method::Stored),
+            ("B.txt", b"second second second", Method::Deflate),
+            ("C.txt", b"third", Method::Stored),
+        ]);
+        // three central headers with their names, then the end record
+        let dir_len = 3 * (46 + "A.txt".len()) + 22;
+        // cut into the data of the last entry, taking the central directory with it
+        let cut = bytes.len() - dir_len - 2;
+        let mut pak = read_pak(bytes[..cut].to_vec());
+        let names: Vec<&str> = pak.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["A.txt", "B.txt"]);
+        assert_eq!(pak.unpack("A.txt").unwrap(), b"first");
+        assert_eq!(pak.unpack("B.txt").unwrap(), b"second second second");
+    }
+
+    #[test]
+    fn zip64_local_header_sizes() {
+        let entry = WrittenEntry {
+            name: "Big.dat".to_string(),
+            method: METHOD_DEFLATE,
+            crc32: 0,
+            compressed_size: 1000,
+            uncompressed_size: 5 << 30,
+            header_offset: 0,
+            modified: PakWriter::new(vec![]).modified,
+        };
+        let header = local_header(&entry);
+        // both sizes defer to the extra field, which has them in full
+        assert_eq!(header[18..26], [0xff; 8]);
+        let extra = &header[30 + entry.name.len()..];
+        assert_eq!(extra[4..12], (5u64 << 30).to_le_bytes());
+        assert_eq!(extra[12..20], 1000u64.to_le_bytes());
+    }
+
+    #[test]
+    fn zip64_entry_count() {
+        let count = 70_000;
+        let mut writer = PakWriter::new(vec![]);
+        for i in 0..count {
+            writer
+                .add(
+                    &format!("{}.txt", i),
+                    i.to_string().as_bytes(),
+                    Method::Stored,
+                )
+                .unwrap();
+        }
+        let mut pak = read_pak(writer.finish().unwrap());
+        assert_eq!(pak.entries().len(), count);
+        for i in [0, 65_534, 65_535, count - 1].iter() {
+            let data = pak.unpack(&format!("{}.txt", i)).unwrap();
+            assert_eq!(data, i.to_string().as_bytes());
+        }
+    }
+}
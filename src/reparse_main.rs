@@ -1,26 +1,84 @@
+mod diagnostics;
+mod game_fs;
+mod localization;
 mod mwo_types;
 mod pak_archive;
+mod pak_diff;
+mod pak_tool;
+mod pak_writer;
 
-use itertools::Itertools;
-use std::io::Read;
+use diagnostics::{Diagnostic, Diagnostics, XmlSource};
+use game_fs::GameFs;
 
-use mwo_types::{Affiliation, Component, Specialness, Variant, Weapon};
+use mwo_types::{
+    Affiliation, Ammo, ArmorType, Component, Engine, EngineKind, Equipment, HSType, Loadout,
+    LoadoutComponent, Movement, OmniPod, OmniPodSet, Specialness, SpeedCurve, StructureType,
+    Upgrade, UpgradeKind, Variant, Weapon, WeaponDoor,
+};
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::path::Path;
 type MyMap<K, V> = std::collections::BTreeMap<K, V>;
 
 fn main() {
-    let game_path = Path::new(&std::env::args().nth(1).expect("pls supply path/to/MechWarrior Online/ ")).join("Game");
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("pak") {
+        if let Err(e) = pak_tool::main(&args[2..]) {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let game_path = Path::new(args.get(1).expect("pls supply path/to/MechWarrior Online/ ")).join("Game");
+
+    let mut diags = Diagnostics::default();
+    let mut game_fs = GameFs::mount(&game_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    for err in game_fs.mount_errors() {
+        diags.push(Diagnostic::new(format!("skipping pak: {}", err)));
+    }
+    for conflict in game_fs.name_conflicts() {
+        diags.push(Diagnostic::new(conflict.to_string()));
+    }
+    // mounting works on any directory, this is where a wrong path shows
+    if !game_fs.exists(r"Libs/Items/Mechs/Mechs.xml") {
+        diags.report(std::io::stderr()).unwrap();
+        eprintln!(
+            "no Libs/Items/Mechs/Mechs.xml in {}, is that a MWO install?",
+            game_path.display()
+        );
+        std::process::exit(1);
+    }
 
-    let weapons = parse_weapons(&game_path);
-    let internals = parse_internals(&game_path);
-    let variants = parse_mechs(&game_path, &internals);
+    let weapons = parse_weapons(&mut game_fs, &mut diags);
+    let weapons = diags.ok(weapons).unwrap_or_default();
+    let ammo = parse_ammo(&mut game_fs, &weapons, &mut diags);
+    let ammo = diags.ok(ammo).unwrap_or_default();
+    let engines = parse_engines(&mut game_fs, &mut diags);
+    let engines = diags.ok(engines).unwrap_or_default();
+    let equipment = parse_equipment(&mut game_fs, &mut diags);
+    let upgrades = parse_upgrades(&mut game_fs, &mut diags);
+    let upgrades = diags.ok(upgrades).unwrap_or_default();
+    let internals = parse_internals(&mut game_fs, &mut diags);
+    let internals = diags.ok(internals).unwrap_or_default();
+    let mechs = parse_mechs(&mut game_fs, &internals, &engines, &mut diags);
+    let mechs = diags.ok(mechs).unwrap_or_default();
 
-    let combined = crate::mwo_types::MechdataCombined2 {
-        mech_variants: variants,
+    let mut combined = crate::mwo_types::MechdataCombined2 {
+        mech_variants: mechs.variants,
+        omnipods: mechs.omnipods,
+        omnipod_sets: mechs.omnipod_sets,
         weapons: weapons.clone(),
+        ammo,
+        engines,
+        equipment,
+        upgrades,
+        localized: Default::default(),
     };
+    localization::localize(&mut game_fs, &mut combined, &mut diags);
     let to_write = vec![
         (
             "static/mechdata_combined.min.json",
@@ -43,66 +101,454 @@ fn main() {
         )
         .unwrap();
     }
+
+    diags.report(std::io::stderr()).unwrap();
 }
 
-pub(crate) fn parse_weapons(game_path: impl AsRef<Path>) -> Vec<Weapon> {
-    let arch_path = game_path.as_ref().join(r"GameData.pak");
-    let mut archive =
-        pak_archive::PakArchive::new(std::fs::File::open(arch_path).unwrap()).unwrap();
-    let wep_contents =
-        String::from_utf8(archive.unpack(r"Libs/Items/Weapons/Weapons.xml")).unwrap();
-    let doc = roxmltree::Document::parse(&wep_contents).unwrap();
+fn parse_faction(src: &XmlSource, node: roxmltree::Node) -> Result<Affiliation, Diagnostic> {
+    match src.attr(node, "faction")? {
+        "InnerSphere" => Ok(Affiliation::InnerSphere),
+        "Clan" => Ok(Affiliation::Clan),
+        other => Err(src.error_at(node, format!("unknown faction {:?}", other))),
+    }
+}
 
-    let weap_list_elem = doc.root().children().next().unwrap();
-    assert_eq!(weap_list_elem.tag_name().name(), "WeaponList");
+pub(crate) fn parse_weapons(
+    game_fs: &mut GameFs,
+    diags: &mut Diagnostics,
+) -> Result<Vec<Weapon>, Diagnostic> {
+    let (src, wep_data) = XmlSource::open(game_fs, r"Libs/Items/Weapons/Weapons.xml")?;
+    let doc = src.parse(&wep_data)?;
+    let weap_list_elem = src.root(&doc, "WeaponList")?;
 
     let mut weapons = vec![];
-    for w in weap_list_elem.children() {
-        if !w.is_element() {
+    for w in weap_list_elem.children().filter(|x| x.is_element()) {
+        // println!("{:?}", w);
+        let skipped = ["DropShipLargePulseLaser", "FakeMachineGun"];
+        if matches!(w.attribute("name"), Some(name) if skipped.contains(&name)) {
             continue;
         }
-        // println!("{:?}", w);
-        assert_eq!(w.tag_name().name(), "Weapon");
+        weapons.extend(diags.ok(parse_weapon(&src, weap_list_elem, w)));
+    }
+
+    Ok(weapons)
+}
 
-        let stats = if let Some(f) = w.attribute("InheritFrom") {
+fn parse_weapon(
+    src: &XmlSource,
+    weap_list_elem: roxmltree::Node,
+    w: roxmltree::Node,
+) -> Result<Weapon, Diagnostic> {
+    src.expect_tag(w, "Weapon")?;
+    let stats = WeaponStats::new(src, weap_list_elem, w)?;
+
+    Ok(Weapon {
+        id: src.parse_attr(w, "id")?,
+        name: src.attr(w, "name")?.into(),
+        hardpoint_aliases: src
+            .attr(w, "HardpointAliases")?
+            .split(",")
+            .map(|x| x.to_string())
+            .collect(),
+        faction: parse_faction(src, w)?,
+        slots: stats.get("slots")?,
+        tons: stats.get("tons")?,
+        cooldown: stats.get("cooldown")?,
+        speed: stats.get("speed")?,
+
+        damage: stats.get("damage")?,
+        heat: stats.get("heat")?,
+        duration: stats.get_or("duration", 0.0)?,
+        min_range: stats.get_or("minRange", 0.0)?,
+        long_range: stats.get("longRange")?,
+        max_range: stats.get("maxRange")?,
+        heat_penalty: stats.get_or("heatPenalty", 0.0)?,
+        heat_penalty_id: stats.get_opt("heatPenaltyID")?,
+        min_heat_penalty_level: stats.get_opt("minHeatPenaltyLevel")?,
+        ammo_type: stats
+            .get_opt::<String>("ammoType")?
+            .filter(|a| !a.is_empty()),
+        ammo_per_shot: stats.get_or("ammoPerShot", 0)?,
+        num_firing: stats.get_or("numFiring", 1)?,
+        num_per_shot: stats.get_or("numPerShot", 1)?,
+        spread: stats.get_or("spread", 0.0)?,
+        jamming_chance: stats.get_or("JammingChance", 0.0)?,
+    })
+}
+
+// A weapon's <WeaponStats>, followed by the ones it inherits from. A weapon
+// with InheritFrom takes everything it doesn't set itself from its parent.
+struct WeaponStats<'s, 'a, 'input> {
+    src: &'s XmlSource,
+    chain: Vec<roxmltree::Node<'a, 'input>>,
+}
+
+impl<'s, 'a, 'input> WeaponStats<'s, 'a, 'input> {
+    fn new(
+        src: &'s XmlSource,
+        weap_list_elem: roxmltree::Node<'a, 'input>,
+        weapon: roxmltree::Node<'a, 'input>,
+    ) -> Result<Self, Diagnostic> {
+        let mut chain = vec![];
+        let mut seen = vec![];
+        let mut current = weapon;
+        loop {
+            chain.extend(
+                current
+                    .children()
+                    .find(|c| c.is_element() && c.tag_name().name() == "WeaponStats"),
+            );
+            let parent_id = match current.attribute("InheritFrom") {
+                Some(id) => id,
+                None => break,
+            };
             // println!("inheriting");
-            weap_list_elem
+            if seen.contains(&parent_id) {
+                return Err(src.error_at(weapon, "InheritFrom goes round in a circle"));
+            }
+            seen.push(parent_id);
+            current = weap_list_elem
                 .children()
-                .find(|w| w.attribute("id") == Some(f))
-                .unwrap()
-        } else {
-            w
+                .find(|w| w.attribute("id") == Some(parent_id))
+                .ok_or_else(|| {
+                    src.error_at(
+                        weapon,
+                        format!("InheritFrom={:?} is not a weapon", parent_id),
+                    )
+                })?;
         }
-        .children()
-        .find(|c| c.tag_name().name() == "WeaponStats")
-        .unwrap();
+        if chain.is_empty() {
+            return Err(src.error_at(weapon, "no <WeaponStats> in here or what it inherits from"));
+        }
+        Ok(Self { src, chain })
+    }
 
-        let weap = Weapon {
-            id: w.attribute("id").unwrap().parse().unwrap(),
-            name: w.attribute("name").unwrap().into(),
-            hardpoint_aliases: w
-                .attribute("HardpointAliases")
-                .unwrap()
-                .split(",")
-                .map(|x| x.to_string())
-                .collect(),
-            faction: match w.attribute("faction").unwrap() {
-                "Clan" => Affiliation::Clan,
-                "InnerSphere" => Affiliation::InnerSphere,
-                other => panic!("{:?}", other),
-            },
-            slots: stats.attribute("slots").unwrap().parse().unwrap(),
-            tons: stats.attribute("tons").unwrap().parse().unwrap(),
-            cooldown: stats.attribute("cooldown").unwrap().parse().unwrap(),
-            speed: stats.attribute("speed").unwrap().parse().unwrap(),
-        };
-        if ["DropShipLargePulseLaser", "FakeMachineGun"].contains(&weap.name.as_str()) {
-            continue;
+    // the game isn't consistent about the case of these
+    fn find(&self, name: &str) -> Option<(roxmltree::Node<'a, 'input>, &'a str)> {
+        self.chain.iter().find_map(|stats| {
+            stats
+                .attributes()
+                .iter()
+                .find(|a| a.name().eq_ignore_ascii_case(name))
+                .map(|a| (*stats, a.value()))
+        })
+    }
+
+    fn get_opt<T>(&self, name: &str) -> Result<Option<T>, Diagnostic>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        match self.find(name) {
+            None => Ok(None),
+            Some((node, value)) => value.parse().map(Some).map_err(|e| {
+                self.src.error_at(
+                    node,
+                    format!("attribute {}={:?} doesn't parse: {}", name, value, e),
+                )
+            }),
+        }
+    }
+
+    fn get_or<T>(&self, name: &str, default: T) -> Result<T, Diagnostic>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        Ok(self.get_opt(name)?.unwrap_or(default))
+    }
+
+    fn get<T>(&self, name: &str) -> Result<T, Diagnostic>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.get_opt(name)?.ok_or_else(|| {
+            self.src
+                .error_at(self.chain[0], format!("missing attribute {:?}", name))
+        })
+    }
+}
+
+fn parse_ammo(
+    game_fs: &mut GameFs,
+    weapons: &[Weapon],
+    diags: &mut Diagnostics,
+) -> Result<Vec<Ammo>, Diagnostic> {
+    let (src, ammo_data) = XmlSource::open(game_fs, r"Libs/Items/Modules/Ammo.xml")?;
+    let doc = src.parse(&ammo_data)?;
+    let ammo_list_elem = src.root(&doc, "ModuleList")?;
+
+    let mut ammo = vec![];
+    for a in ammo_list_elem.children().filter(|x| x.is_element()) {
+        let item = (|| {
+            let module_stats = src.only_child(a, "ModuleStats")?;
+            let ammo_stats = src.only_child(a, "AmmoTypeStats")?;
+            let ammo_type: String = src.attr(ammo_stats, "type")?.to_string();
+            let tons: f32 = src.parse_attr(module_stats, "tons")?;
+            let rounds: i32 = src.parse_attr(ammo_stats, "numShots")?;
+            Ok(Ammo {
+                id: src.parse_attr(a, "id")?,
+                name: src.attr(a, "name")?.to_string(),
+                faction: parse_faction(&src, a)?,
+                slots: src.parse_attr(module_stats, "slots")?,
+                tons,
+                health: src.parse_attr(module_stats, "health")?,
+                rounds,
+                rounds_per_ton: if tons > 0.0 {
+                    rounds as f32 / tons
+                } else {
+                    0.0
+                },
+                weapon_ids: weapons
+                    .iter()
+                    .filter(|w| w.ammo_type.as_deref() == Some(ammo_type.as_str()))
+                    .map(|w| w.id)
+                    .collect(),
+                ammo_type,
+            })
+        })();
+        ammo.extend(diags.ok(item));
+    }
+
+    for weapon in weapons {
+        if let Some(ammo_type) = &weapon.ammo_type {
+            if !ammo.iter().any(|a| &a.ammo_type == ammo_type) {
+                diags.push(src.error(format!(
+                    "no ammo of type {:?}, which {} uses",
+                    ammo_type, weapon.name
+                )));
+            }
+        }
+    }
+
+    Ok(ammo)
+}
+
+fn parse_engines(game_fs: &mut GameFs, diags: &mut Diagnostics) -> Result<Vec<Engine>, Diagnostic> {
+    let (src, engine_data) = XmlSource::open(game_fs, r"Libs/Items/Modules/Engines.xml")?;
+    let doc = src.parse(&engine_data)?;
+    let engine_list_elem = src.root(&doc, "ModuleList")?;
+
+    let mut engines = vec![];
+    for e in engine_list_elem.children().filter(|x| x.is_element()) {
+        let engine = (|| {
+            let module_stats = src.only_child(e, "ModuleStats")?;
+            let engine_stats = src.only_child(e, "EngineStats")?;
+            let name = src.attr(e, "name")?;
+            Ok(Engine {
+                id: src.parse_attr(e, "id")?,
+                name: name.to_string(),
+                faction: parse_faction(&src, e)?,
+                kind: engine_kind(name),
+                rating: src.parse_attr(engine_stats, "rating")?,
+                tons: src.parse_attr(module_stats, "tons")?,
+                slots: src.parse_attr(module_stats, "slots")?,
+                side_slots: src.parse_attr(engine_stats, "sideSlots")?,
+                heat_sinks: src.parse_attr(engine_stats, "heatsinks")?,
+                heat_sink_slots: src.parse_attr(engine_stats, "heatsinkslots")?,
+            })
+        })();
+        if let Some(engine) = diags.ok(engine) {
+            // still counts for its weight, so it's kept
+            if engine.kind.is_none() {
+                diags.push(src.error_at(
+                    e,
+                    format!("can't tell what kind of engine {:?} is", engine.name),
+                ));
+            }
+            engines.push(engine);
         }
-        weapons.push(weap);
     }
 
-    weapons
+    Ok(engines)
+}
+
+// Only the name says, e.g. `Engine_ClanXL_300`
+fn engine_kind(name: &str) -> Option<EngineKind> {
+    let kind: String = name
+        .to_lowercase()
+        .split('_')
+        .filter(|part| *part != "engine" && part.parse::<i32>().is_err())
+        .collect();
+    match kind.as_str() {
+        "std" => Some(EngineKind::Std),
+        "xl" => Some(EngineKind::Xl),
+        "light" => Some(EngineKind::Light),
+        "clanxl" => Some(EngineKind::ClanXl),
+        _ => None,
+    }
+}
+
+// Everything in Libs/Items/Modules that doesn't have a parser of its own.
+fn parse_equipment(game_fs: &mut GameFs, diags: &mut Diagnostics) -> Vec<Equipment> {
+    let handled_elsewhere = ["ammo.xml", "engines.xml", "internals.xml"];
+    let filenames: Vec<String> = game_fs
+        .list(r"Libs/Items/Modules/")
+        .into_iter()
+        .filter(|f| f.to_lowercase().ends_with(".xml"))
+        .filter(|f| {
+            let basename = f.rsplit(&['/', '\\'][..]).next().unwrap_or(f);
+            !handled_elsewhere.contains(&basename.to_lowercase().as_str())
+        })
+        .map(|f| f.to_string())
+        .collect();
+
+    let mut equipment = vec![];
+    for filename in filenames {
+        let items = XmlSource::open(game_fs, &filename).and_then(|(src, data)| {
+            let doc = src.parse(&data)?;
+            let list_elem = src.root(&doc, "ModuleList")?;
+            for m in list_elem.children().filter(|x| x.is_element()) {
+                equipment.extend(diags.ok(parse_equipment_item(&src, m)).flatten());
+            }
+            Ok(())
+        });
+        diags.ok(items);
+    }
+    equipment
+}
+
+// None for things that aren't equipment at all.
+fn parse_equipment_item(
+    src: &XmlSource,
+    m: roxmltree::Node,
+) -> Result<Option<Equipment>, Diagnostic> {
+    let kind_stats = m.children().find(|c| {
+        let tag = c.tag_name().name();
+        c.is_element() && tag.ends_with("Stats") && tag != "ModuleStats"
+    });
+    let kind_stats = match kind_stats {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let module_stats = src.only_child(m, "ModuleStats")?;
+    let allowed_locations = match module_stats.attribute("components") {
+        Some(c) => c
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect(),
+        None => vec![],
+    };
+    let stats = kind_stats
+        .attributes()
+        .iter()
+        .filter_map(|a| Some((a.name().to_string(), a.value().parse().ok()?)))
+        .collect();
+
+    Ok(Some(Equipment {
+        id: src.parse_attr(m, "id")?,
+        name: src.attr(m, "name")?.to_string(),
+        faction: parse_faction(src, m)?,
+        kind: kind_stats
+            .tag_name()
+            .name()
+            .trim_end_matches("Stats")
+            .to_string(),
+        slots: src.parse_attr(module_stats, "slots")?,
+        tons: src.parse_attr(module_stats, "tons")?,
+        health: src.parse_attr(module_stats, "health")?,
+        allowed_locations,
+        stats,
+    }))
+}
+
+fn parse_upgrades(
+    game_fs: &mut GameFs,
+    diags: &mut Diagnostics,
+) -> Result<Vec<Upgrade>, Diagnostic> {
+    let (src, upgrade_data) =
+        XmlSource::open(game_fs, r"Libs/Items/UpgradeTypes/UpgradeTypes.xml")?;
+    let doc = src.parse(&upgrade_data)?;
+    let upgrade_list_elem = src.root(&doc, "UpgradeTypes")?;
+
+    let mut upgrades = vec![];
+    for u in upgrade_list_elem.children().filter(|x| x.is_element()) {
+        upgrades.extend(diags.ok(parse_upgrade(&src, u)).flatten());
+    }
+
+    Ok(upgrades)
+}
+
+// None for the upgrades that don't change what the mech weighs or holds,
+// like Artemis.
+fn parse_upgrade(src: &XmlSource, u: roxmltree::Node) -> Result<Option<Upgrade>, Diagnostic> {
+    let find_stats = |tag: &str| {
+        u.children()
+            .find(|c| c.is_element() && c.tag_name().name() == tag)
+    };
+    let name = src.attr(u, "name")?;
+    let unknown = || src.error_at(u, format!("can't tell what kind of upgrade {} is", name));
+    let kind = if let Some(stats) = find_stats("StructureTypeStats") {
+        UpgradeKind::Structure {
+            kind: structure_type(name).ok_or_else(unknown)?,
+            tons_per_mech_ton: src.parse_attr(stats, "weightPerTon")?,
+        }
+    } else if let Some(stats) = find_stats("ArmorTypeStats") {
+        UpgradeKind::Armor {
+            kind: armor_type(name).ok_or_else(unknown)?,
+            armor_per_ton: src.parse_attr(stats, "armorPerTon")?,
+        }
+    } else if let Some(stats) = find_stats("HeatSinkTypeStats") {
+        UpgradeKind::HeatSinks {
+            kind: hs_type(name).ok_or_else(unknown)?,
+            heat_sink_id: src.parse_attr(stats, "compatibleHeatSink")?,
+        }
+    } else {
+        return Ok(None);
+    };
+    let upgrade_stats = src.only_child(u, "UpgradeTypeStats")?;
+
+    Ok(Some(Upgrade {
+        id: src.parse_attr(u, "id")?,
+        name: name.to_string(),
+        faction: parse_faction(src, u)?,
+        slots: src.parse_attr(upgrade_stats, "slots")?,
+        kind,
+    }))
+}
+
+// Again only the name says, e.g. `ClanEndoSteelStructure`
+fn structure_type(name: &str) -> Option<StructureType> {
+    let name = name.to_lowercase();
+    if name.contains("endo") {
+        Some(StructureType::Endo)
+    } else if name.contains("standard") {
+        Some(StructureType::Std)
+    } else {
+        None
+    }
+}
+
+fn armor_type(name: &str) -> Option<ArmorType> {
+    let name = name.to_lowercase();
+    if name.contains("lightferro") {
+        Some(ArmorType::LightFerro)
+    } else if name.contains("ferro") {
+        Some(ArmorType::Ferro)
+    } else if name.contains("stealth") {
+        Some(ArmorType::Stealth)
+    } else if name.contains("reactive") {
+        Some(ArmorType::Reactive)
+    } else if name.contains("reflective") {
+        Some(ArmorType::Reflective)
+    } else if name.contains("standard") {
+        Some(ArmorType::Std)
+    } else {
+        None
+    }
+}
+
+fn hs_type(name: &str) -> Option<HSType> {
+    let name = name.to_lowercase();
+    if name.contains("double") {
+        Some(HSType::Double)
+    } else if name.contains("single") || name.contains("standard") {
+        Some(HSType::Single)
+    } else {
+        None
+    }
 }
 
 struct Internal {
@@ -110,32 +556,28 @@ struct Internal {
     slots: i32,
 }
 
-fn parse_internals(game_path: impl AsRef<Path>) -> Vec<Internal> {
-    let arch_path = game_path.as_ref().join(r"GameData.pak");
-    let mut archive =
-        pak_archive::PakArchive::new(std::fs::File::open(arch_path).unwrap()).unwrap();
-    let int_contents =
-        String::from_utf8(archive.unpack(r"Libs/Items/Modules/Internals.xml")).unwrap();
-    let doc = roxmltree::Document::parse(&int_contents).unwrap();
-
-    let int_list_elem = doc.root().children().next().unwrap();
-    assert_eq!(int_list_elem.tag_name().name(), "ModuleList");
+fn parse_internals(
+    game_fs: &mut GameFs,
+    diags: &mut Diagnostics,
+) -> Result<Vec<Internal>, Diagnostic> {
+    let (src, int_data) = XmlSource::open(game_fs, r"Libs/Items/Modules/Internals.xml")?;
+    let doc = src.parse(&int_data)?;
+    let int_list_elem = src.root(&doc, "ModuleList")?;
 
     let mut internals = vec![];
     for int in int_list_elem.children().filter(|x| x.is_element()) {
-        assert_eq!(int.tag_name().name(), "Internal");
-        let module_stats = int
-            .children()
-            .filter(|x| x.is_element() && x.tag_name().name() == "ModuleStats")
-            .exactly_one()
-            .unwrap();
-        internals.push(Internal {
-            id: int.attribute("id").unwrap().parse().unwrap(),
-            slots: module_stats.attribute("slots").unwrap().parse().unwrap(),
-        })
+        let internal = (|| {
+            src.expect_tag(int, "Internal")?;
+            let module_stats = src.only_child(int, "ModuleStats")?;
+            Ok(Internal {
+                id: src.parse_attr(int, "id")?,
+                slots: src.parse_attr(module_stats, "slots")?,
+            })
+        })();
+        internals.extend(diags.ok(internal));
     }
 
-    internals
+    Ok(internals)
 }
 
 struct MechListElement {
@@ -145,46 +587,43 @@ struct MechListElement {
     variant: String,
 }
 
-fn parse_mechs(game_path: impl AsRef<Path>, internals: &[Internal]) -> Vec<Variant> {
-    let game_path = game_path.as_ref();
-    let gamedata_pak_path = game_path.join(r"GameData.pak");
-    let mut archive =
-        pak_archive::PakArchive::new(std::fs::File::open(gamedata_pak_path).unwrap()).unwrap();
-    let mech_list_contents =
-        String::from_utf8(archive.unpack(r"Libs/Items/Mechs/Mechs.xml")).unwrap();
+fn parse_mechs(
+    game_fs: &mut GameFs,
+    internals: &[Internal],
+    engines: &[Engine],
+    diags: &mut Diagnostics,
+) -> Result<MechData, Diagnostic> {
+    let (src, mech_list_data) = XmlSource::open(game_fs, r"Libs/Items/Mechs/Mechs.xml")?;
+    let doc = src.parse(&mech_list_data)?;
+    let ml = src.root(&doc, "MechList")?;
 
     let mut mech_list = vec![];
-    let doc = roxmltree::Document::parse(&mech_list_contents).unwrap();
-    let root = doc.root();
-
-    let ml: roxmltree::Node = root
-        .children()
-        .filter(|x| x.is_element())
-        .exactly_one()
-        .unwrap();
     for mech in ml.children().filter(|x| x.is_element()) {
-        assert_eq!(mech.tag_name().name(), "Mech");
-        mech_list.push(MechListElement {
-            // id: mech.attribute("id").unwrap().parse().unwrap(),
-            faction: match mech.attribute("faction").unwrap() {
-                "InnerSphere" => Affiliation::InnerSphere,
-                "Clan" => Affiliation::Clan,
-                _ => panic!(),
-            },
-            chassis: mech.attribute("chassis").unwrap().parse().unwrap(),
-            variant: mech.attribute("name").unwrap().parse().unwrap(),
-        })
+        let element = (|| {
+            src.expect_tag(mech, "Mech")?;
+            Ok(MechListElement {
+                // id: mech.attribute("id").unwrap().parse().unwrap(),
+                faction: parse_faction(&src, mech)?,
+                chassis: src.attr(mech, "chassis")?.to_string(),
+                variant: src.attr(mech, "name")?.to_string(),
+            })
+        })();
+        mech_list.extend(diags.ok(element));
     }
     let chassis_set = mech_list
         .iter()
         .map(|x| x.chassis.to_string())
         .collect::<std::collections::BTreeSet<_>>();
-    let mut variants = vec![];
+    let mut mechs = MechData::default();
     for chassis in chassis_set {
         // println!("{:?}", chassis);
-        variants.extend(parse_mech_chassis(
-            game_path, &chassis, internals, &mech_list,
-        ));
+        let chassis_data =
+            parse_mech_chassis(game_fs, &chassis, internals, engines, &mech_list, diags);
+        if let Some(data) = diags.ok(chassis_data) {
+            mechs.variants.extend(data.variants);
+            mechs.omnipods.extend(data.omnipods);
+            mechs.omnipod_sets.extend(data.omnipod_sets);
+        }
     }
 
     {
@@ -193,122 +632,224 @@ fn parse_mechs(game_path: impl AsRef<Path>, internals: &[Internal]) -> Vec<Varia
             .iter()
             .map(|x| x.variant.to_string().to_lowercase())
             .collect();
-        let vars_found: BTreeSet<_> = variants
+        let vars_found: BTreeSet<_> = mechs
+            .variants
             .iter()
             .map(|x| x.variant_name.to_string().to_lowercase())
             .collect();
-        assert_eq!(vars_found, vars_declared);
+        for missing in vars_declared.difference(&vars_found) {
+            diags.push(src.error(format!(
+                "variant {:?} is listed but was not extracted",
+                missing
+            )));
+        }
     }
-    variants
+    Ok(mechs)
+}
+
+#[derive(Default)]
+struct MechData {
+    variants: Vec<Variant>,
+    // only Clan OmniMechs have these
+    omnipods: Vec<OmniPod>,
+    omnipod_sets: Vec<OmniPodSet>,
 }
 
 fn parse_mech_chassis(
-    game_path: impl AsRef<Path>,
+    game_fs: &mut GameFs,
     chassis: &str,
     internals: &[Internal],
+    engines: &[Engine],
     mechlist: &Vec<MechListElement>,
-) -> Vec<Variant> {
-    let game_path = game_path.as_ref();
-    let pak_path = game_path.join(format!("mechs/{}.pak", chassis));
-    let mut pak_contents = vec![];
-    std::fs::File::open(pak_path)
-        .unwrap()
-        .read_to_end(&mut pak_contents)
-        .unwrap();
+    diags: &mut Diagnostics,
+) -> Result<MechData, Diagnostic> {
+    // the chassis pak tells us which files belong to it, but they're read
+    // through the game fs in case a patch pak overrides them
+    let pak_name = format!("mechs/{}.pak", chassis);
+    let archive = game_fs
+        .archive(&pak_name)
+        .ok_or_else(|| Diagnostic::new(format!("no {} for chassis {:?}", pak_name, chassis)))?;
+    let hardpoints_filename = archive
+        .glob("*-hardpoints.xml")
+        .map(|e| e.name.clone())
+        .last()
+        .ok_or_else(|| Diagnostic {
+            pak: Some(archive.path().to_path_buf()),
+            ..Diagnostic::new("no *-hardpoints.xml in here")
+        })?;
+    let omnipods_filename = archive
+        .glob("*-omnipods.xml")
+        .map(|e| e.name.clone())
+        .last();
+    let mdf_filenames: Vec<String> = archive.glob("*.mdf").map(|e| e.name.clone()).collect();
 
-    // let arch = rpak::PakArchive::from_bytes(&pak_contents).unwrap();
-    // let files = pak_archive::unpak(std::io::Cursor::new(pak_contents)).unwrap();
-    let mut archive = pak_archive::PakArchive::new(std::io::Cursor::new(pak_contents)).unwrap();
-    let mut hardpoints_xml = None;
-    let mut variants_xmls = vec![];
-    for filename in archive.file_list() {
-        if filename.ends_with("-hardpoints.xml") {
-            hardpoints_xml = Some(String::from_utf8(archive.unpack(&filename)).unwrap());
-            continue;
-        }
-        if filename.ends_with(".mdf") {
-            variants_xmls.push((
-                filename.to_string(),
-                String::from_utf8(archive.unpack(&filename)).unwrap(),
-            ));
-        }
-    }
-    let hardpoints_xml = hardpoints_xml.unwrap();
-
-    let hardpoints = parse_hardpoints_def(&hardpoints_xml);
+    let (hardpoints_src, hardpoints_data) = XmlSource::open(game_fs, &hardpoints_filename)?;
+    let hardpoints_doc = hardpoints_src.parse(&hardpoints_data)?;
+    let hardpoints = parse_hardpoints_def(&hardpoints_src, &hardpoints_doc, diags)?;
     // println!("{:?}", hardpoints);
     let mut variants = vec![];
-    for (filename, variant) in variants_xmls {
+    for filename in mdf_filenames {
         // println!("hi - {:?}", filename);
         let variant_name = Path::new(&filename)
             .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .split(".")
-            .next()
-            .unwrap();
-        let var = parse_mech_variant(variant_name, &variant, &hardpoints, internals, mechlist);
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        let variant_name = variant_name.split(".").next().unwrap_or_default();
+        let var = XmlSource::open(game_fs, &filename).and_then(|(src, data)| {
+            let doc = src.parse(&data)?;
+            let mut variant = parse_mech_variant(
+                &src,
+                &doc,
+                variant_name,
+                &hardpoints,
+                internals,
+                mechlist,
+                diags,
+            )?;
+            // a broken loadout shouldn't cost us the whole variant
+            variant.loadout = diags.ok(parse_loadout(&src, &doc, engines)).flatten();
+            Ok(variant)
+        });
         // println!("{:?}", var);
-        variants.push(var)
+        variants.extend(diags.ok(var));
     }
-    variants
+
+    let (omnipods, omnipod_sets) = match omnipods_filename {
+        Some(filename) => {
+            let pods = XmlSource::open(game_fs, &filename).and_then(|(src, data)| {
+                let doc = src.parse(&data)?;
+                parse_omnipods(
+                    &src,
+                    &doc,
+                    chassis,
+                    &variants,
+                    &hardpoints,
+                    internals,
+                    diags,
+                )
+            });
+            diags.ok(pods).unwrap_or_default()
+        }
+        None => Default::default(),
+    };
+    Ok(MechData {
+        variants,
+        omnipods,
+        omnipod_sets,
+    })
 }
 
 #[derive(Debug)]
 struct HardpointDefs {
     // hardpoint id -> slot count
     slot_count: MyMap<i32, i32>,
+    // hardpoint id -> the doors over it
+    doors: MyMap<i32, DoorDef>,
 }
 
-fn parse_hardpoints_def(contents: &str) -> HardpointDefs {
-    let doc = roxmltree::Document::parse(contents).unwrap();
-    let root = doc.root();
-    let hardpoints_elem: roxmltree::Node = root
-        .children()
-        .filter(|x| x.is_element())
-        .exactly_one()
-        .unwrap();
-    assert_eq!(hardpoints_elem.tag_name().name(), "Hardpoints");
+#[derive(Debug)]
+struct DoorDef {
+    door_set_id: i32,
+    open_duration: f32,
+    close_duration: f32,
+    delay_before_close: f32,
+}
+
+fn parse_hardpoints_def(
+    src: &XmlSource,
+    doc: &roxmltree::Document,
+    diags: &mut Diagnostics,
+) -> Result<HardpointDefs, Diagnostic> {
+    let hardpoints_elem = src.root(doc, "Hardpoints")?;
 
     let mut result: MyMap<i32, i32> = Default::default();
+    // (hardpoint id, its doors, the <WeaponDoor>)
+    let mut doors = vec![];
     for hp in hardpoints_elem.children().filter(|x| x.is_element()) {
         match hp.tag_name().name() {
             "Hardpoint" => {}
             "WeaponDoorSet" => {
-                // TODO: idk
+                doors.extend(diags.ok(parse_weapon_door_set(src, hp)).unwrap_or_default());
+                continue;
+            }
+            other => {
+                diags.push(src.error_at(hp, format!("unknown hardpoint element <{}>", other)));
                 continue;
             }
-            _ => panic!(),
         }
-        assert_eq!(hp.tag_name().name(), "Hardpoint");
-        let id: i32 = hp.attribute("id").unwrap().parse().unwrap();
+        let id: i32 = match diags.ok(src.parse_attr(hp, "id")) {
+            Some(id) => id,
+            None => continue,
+        };
         let mut amt = 0;
         for slot in hp.children().filter(|x| x.is_element()) {
-            assert_eq!(slot.tag_name().name(), "WeaponSlot");
+            if slot.tag_name().name() != "WeaponSlot" {
+                diags.push(src.error_at(slot, "not counting this as a weapon slot"));
+                continue;
+            }
             amt += 1
         }
         result.insert(id, amt);
     }
 
-    HardpointDefs { slot_count: result }
+    // doors can come before the hardpoints they're for
+    let mut door_defs = MyMap::new();
+    for (hardpoint_id, door, door_elem) in doors {
+        if !result.contains_key(&hardpoint_id) {
+            diags.push(src.error_at(
+                door_elem,
+                format!("hardpoint {} is not in this file", hardpoint_id),
+            ));
+            continue;
+        }
+        match door_defs.entry(hardpoint_id) {
+            std::collections::btree_map::Entry::Vacant(e) => {
+                e.insert(door);
+            }
+            std::collections::btree_map::Entry::Occupied(_) => {
+                diags.push(src.error_at(
+                    door_elem,
+                    format!("hardpoint {} already has doors", hardpoint_id),
+                ));
+            }
+        }
+    }
+
+    Ok(HardpointDefs {
+        slot_count: result,
+        doors: door_defs,
+    })
+}
+
+fn parse_weapon_door_set<'a, 'input>(
+    src: &XmlSource,
+    set_elem: roxmltree::Node<'a, 'input>,
+) -> Result<Vec<(i32, DoorDef, roxmltree::Node<'a, 'input>)>, Diagnostic> {
+    let door_set_id: i32 = src.parse_attr(set_elem, "ID")?;
+    let mut doors = vec![];
+    for door in set_elem.children().filter(|x| x.is_element()) {
+        src.expect_tag(door, "WeaponDoor")?;
+        let def = DoorDef {
+            door_set_id,
+            open_duration: src.parse_attr(door, "OpenDuration")?,
+            close_duration: src.parse_attr(door, "CloseDuration")?,
+            delay_before_close: src.parse_attr(door, "DelayBeforeClose")?,
+        };
+        doors.push((src.parse_attr(door, "HardpointID")?, def, door));
+    }
+    Ok(doors)
 }
 
 fn parse_mech_variant(
+    src: &XmlSource,
+    doc: &roxmltree::Document,
     variant_name: &str,
-    variant_content: &str,
     hardpoint_defs: &HardpointDefs,
     internals: &[Internal],
     mechlist: &[MechListElement],
-) -> Variant {
-    let doc = roxmltree::Document::parse(variant_content).unwrap();
-    let root = doc.root();
-    let mech_def_elem: roxmltree::Node = root
-        .children()
-        .filter(|x| x.is_element())
-        .exactly_one()
-        .unwrap();
-    assert_eq!(mech_def_elem.tag_name().name(), "MechDefinition");
+    diags: &mut Diagnostics,
+) -> Result<Variant, Diagnostic> {
+    let mech_def_elem = src.root(doc, "MechDefinition")?;
 
     struct VariantData {
         display_name: String,
@@ -321,18 +862,14 @@ fn parse_mech_variant(
         specialness: Specialness,
     }
 
-    let melem = mech_def_elem
-        .children()
-        .filter(|x| x.is_element() && x.tag_name().name() == "Mech")
-        .exactly_one()
-        .unwrap();
+    let melem = src.only_child(mech_def_elem, "Mech")?;
     let variant_data = VariantData {
-        display_name: melem.attribute("Variant").expect("no variant").to_string(),
-        max_tons: melem.attribute("MaxTons").unwrap().parse().unwrap(),
-        base_tons: melem.attribute("BaseTons").unwrap().parse().unwrap(),
-        max_jj: melem.attribute("MaxJumpJets").unwrap().parse().unwrap(),
-        engine_min: melem.attribute("MinEngineRating").unwrap().parse().unwrap(),
-        engine_max: melem.attribute("MaxEngineRating").unwrap().parse().unwrap(),
+        display_name: src.attr(melem, "Variant")?.to_string(),
+        max_tons: src.parse_attr(melem, "MaxTons")?,
+        base_tons: src.parse_attr(melem, "BaseTons")?,
+        max_jj: src.parse_attr(melem, "MaxJumpJets")?,
+        engine_min: src.parse_attr(melem, "MinEngineRating")?,
+        engine_max: src.parse_attr(melem, "MaxEngineRating")?,
         specialness: melem
             .attribute("VariantType")
             .map(|typ| match typ {
@@ -342,64 +879,44 @@ fn parse_mech_variant(
                 "Special" => Specialness::Special,
                 "Phoenix" => Specialness::Special,
                 "Sarah" => Specialness::Special,
-                x => panic!("VariantType {:?} is idk", x),
+                x => {
+                    diags.push(src.error_at(
+                        melem,
+                        format!("VariantType {:?} is idk, calling it Special", x),
+                    ));
+                    Specialness::Special
+                }
             })
             .unwrap_or(Specialness::Normal),
     };
 
+    // the rest of the variant is still good without it
+    let movement = src
+        .only_child(mech_def_elem, "MovementTuningConfiguration")
+        .and_then(|elem| parse_movement(src, elem));
+    let movement = diags.ok(movement);
+
     let mut components = MyMap::<String, Component>::new();
-    let complist_elem: roxmltree::Node = mech_def_elem
-        .children()
-        .filter(|x| x.is_element() && x.tag_name().name() == "ComponentList")
-        .exactly_one()
-        .unwrap();
+    let complist_elem = src.only_child(mech_def_elem, "ComponentList")?;
     for comp_elem in complist_elem.children().filter(|x| x.is_element()) {
-        assert_eq!(comp_elem.tag_name().name(), "Component");
-        let comp_name = comp_elem.attribute("Name").unwrap().to_string();
-        let hardpoint_count: BTreeMap<_, _> = comp_elem
-            .children()
-            .filter(|x| x.tag_name().name() == "Hardpoint")
-            .map(|hp| {
-                (
-                    hp.attribute("ID").unwrap().parse::<i32>().unwrap(),
-                    hp.attribute("Type").unwrap().parse::<u8>().unwrap(),
-                )
-            })
-            .map(|(id, typ)| (typ, hardpoint_defs.slot_count[&id]))
-            .collect();
-        let base_slots: i32 = comp_elem.attribute("Slots").unwrap().parse().unwrap();
-        let internal_ids: Vec<i32> = comp_elem
-            .children()
-            .filter(|x| x.tag_name().name() == "Internal")
-            .map(|internal| {
-                internal
-                    .attribute("ItemID")
-                    .unwrap()
-                    .parse::<i32>()
-                    .unwrap()
-            })
-            .collect();
-        let effective_slots: i32 = base_slots
-            - internal_ids
-                .iter()
-                .map(|iid| internals.iter().find(|int| int.id == *iid).unwrap().slots)
-                .sum::<i32>();
-        let comp = Component {
-            base_slots,
-            effective_slots,
-            hp: comp_elem.attribute("HP").unwrap().parse().unwrap(),
-            internal_ids,
-            hardpoint_count,
-            can_equip_ecm: comp_elem
-                .attribute("CanEquipECM")
-                .map(|x| x.parse::<i32>().unwrap() != 0)
-                .unwrap_or(false),
-        };
+        src.expect_tag(comp_elem, "Component")?;
+        let comp_name = src.attr(comp_elem, "Name")?.to_string();
+        let comp = parse_component(
+            src,
+            comp_elem,
+            src.parse_attr(comp_elem, "Slots")?,
+            src.parse_attr(comp_elem, "HP")?,
+            hardpoint_defs,
+            internals,
+        )?;
         if comp_name.ends_with("_rear") {
-            assert_eq!(comp.base_slots, 0);
-            assert_eq!(comp.hp, 0);
-            assert!(comp.internal_ids.is_empty());
-            assert!(comp.hardpoint_count.is_empty());
+            let is_empty = comp.base_slots == 0
+                && comp.hp == 0
+                && comp.internal_ids.is_empty()
+                && comp.hardpoint_count.is_empty();
+            if !is_empty {
+                diags.push(src.error_at(comp_elem, "rear component has stuff in it, ignoring"));
+            }
             continue;
         }
         components.insert(comp_name, comp);
@@ -408,25 +925,23 @@ fn parse_mech_variant(
         .children()
         .filter(|x| x.is_element() && x.tag_name().name() == "QuirkList");
     let (quirk_list_tmp1, quirk_list_tmp2) = (quirk_list_tmp.next(), quirk_list_tmp.next());
-    assert_eq!(quirk_list_tmp2, None);
-    let quirk_list: Vec<(String, f32)> = if let Some(ql) = quirk_list_tmp1 {
-        ql.children()
-            .filter(|x| x.is_element())
-            .map(|q: roxmltree::Node| {
-                assert_eq!(q.tag_name().name(), "Quirk");
-                (
-                    q.attribute("name").unwrap().to_string(),
-                    q.attribute("value").unwrap().parse::<f32>().unwrap(),
-                )
-            })
-            .collect::<Vec<_>>()
-    } else {
-        vec![]
-    };
+    if let Some(extra) = quirk_list_tmp2 {
+        return Err(src.error_at(extra, "more than one <QuirkList>"));
+    }
+    let mut quirk_list: Vec<(String, f32)> = vec![];
+    if let Some(ql) = quirk_list_tmp1 {
+        for q in ql.children().filter(|x| x.is_element()) {
+            let quirk = src.expect_tag(q, "Quirk").and_then(|_| parse_quirk(src, q));
+            quirk_list.extend(diags.ok(quirk));
+        }
+    }
 
-    let mechlist_item = mechlist.iter().find(|x| x.variant == variant_name).unwrap();
+    let mechlist_item = mechlist
+        .iter()
+        .find(|x| x.variant == variant_name)
+        .ok_or_else(|| src.error(format!("variant {:?} is not in Mechs.xml", variant_name)))?;
 
-    Variant {
+    Ok(Variant {
         chassis: mechlist_item.chassis.clone(),
         variant_name: variant_name.to_string(),
         display_name: variant_data.display_name,
@@ -440,5 +955,296 @@ fn parse_mech_variant(
 
         components,
         quirks: quirk_list,
+        movement,
+        // see parse_loadout
+        loadout: None,
+    })
+}
+
+fn parse_movement(src: &XmlSource, elem: roxmltree::Node) -> Result<Movement, Diagnostic> {
+    // e.g. `TurnLerpLowSpeed`, `TurnLerpLowRate` and so on for "TurnLerp"
+    let curve = |prefix: &str| -> Result<SpeedCurve, Diagnostic> {
+        let get = |name: &str| src.parse_attr(elem, &format!("{}{}", prefix, name));
+        Ok(SpeedCurve {
+            low_speed: get("LowSpeed")?,
+            mid_speed: get("MidSpeed")?,
+            high_speed: get("HighSpeed")?,
+            low_rate: get("LowRate")?,
+            mid_rate: get("MidRate")?,
+            high_rate: get("HighRate")?,
+        })
+    };
+    Ok(Movement {
+        archetype: src.attr(elem, "MovementArchetype")?.to_string(),
+        max_speed_factor: src.parse_attr(elem, "MaxMovementSpeed")?,
+        reverse_speed_multiplier: src.parse_attr(elem, "ReverseSpeedMultiplier")?,
+        torso_yaw_speed: src.parse_attr(elem, "TorsoTurnSpeedYaw")?,
+        torso_pitch_speed: src.parse_attr(elem, "TorsoTurnSpeedPitch")?,
+        arm_yaw_speed: src.parse_attr(elem, "ArmTurnSpeedYaw")?,
+        arm_pitch_speed: src.parse_attr(elem, "ArmTurnSpeedPitch")?,
+        max_torso_yaw: src.parse_attr(elem, "MaxTorsoAngleYaw")?,
+        max_torso_pitch: src.parse_attr(elem, "MaxTorsoAnglePitch")?,
+        max_arm_yaw: src.parse_attr(elem, "MaxArmRotationYaw")?,
+        max_arm_pitch: src.parse_attr(elem, "MaxArmRotationPitch")?,
+        turn_rate: curve("TurnLerp")?,
+        acceleration: curve("AccelLerp")?,
+        deceleration: curve("DecelLerp")?,
+    })
+}
+
+// The stock loadout in a mdf, if it has one. The engine is just another
+// module in there, so it's told apart by its id.
+fn parse_loadout(
+    src: &XmlSource,
+    doc: &roxmltree::Document,
+    engines: &[Engine],
+) -> Result<Option<Loadout>, Diagnostic> {
+    let mech_def_elem = src.root(doc, "MechDefinition")?;
+    let loadout_elem = match mech_def_elem
+        .children()
+        .find(|x| x.is_element() && x.tag_name().name() == "Loadout")
+    {
+        Some(l) => l,
+        None => return Ok(None),
+    };
+    let upgrades_elem = src.only_child(loadout_elem, "Upgrades")?;
+    let upgrade_id = |tag: &str| -> Result<i32, Diagnostic> {
+        let upgrade = src.only_child(upgrades_elem, tag)?;
+        src.parse_attr(upgrade, "ItemID")
+    };
+
+    let mut engine_id = None;
+    let mut components = BTreeMap::<String, LoadoutComponent>::new();
+    let complist_elem = src.only_child(loadout_elem, "ComponentList")?;
+    for comp_elem in complist_elem.children().filter(|x| x.is_element()) {
+        src.expect_tag(comp_elem, "component")?;
+        let comp_name = src.attr(comp_elem, "Name")?;
+        let armor: i32 = src.parse_attr(comp_elem, "Armor")?;
+        // rear armor goes with the front, like in `Variant::components`
+        if let Some(front) = comp_name.strip_suffix("_rear") {
+            components.entry(front.to_string()).or_default().rear_armor = armor;
+            continue;
+        }
+        let comp = components.entry(comp_name.to_string()).or_default();
+        comp.armor = armor;
+        for item in comp_elem.children().filter(|x| x.is_element()) {
+            let id: i32 = src.parse_attr(item, "ItemID")?;
+            match item.tag_name().name() {
+                "Weapon" => comp.weapon_ids.push(id),
+                "Module" if engines.iter().any(|e| e.id == id) => {
+                    if engine_id.replace(id).is_some() {
+                        return Err(src.error_at(item, "more than one engine"));
+                    }
+                }
+                "Module" => comp.module_ids.push(id),
+                other => {
+                    return Err(src.error_at(item, format!("unknown loadout item <{}>", other)))
+                }
+            }
+        }
     }
+
+    Ok(Some(Loadout {
+        engine_id,
+        structure_id: upgrade_id("Structure")?,
+        armor_id: upgrade_id("Armor")?,
+        heat_sinks_id: upgrade_id("HeatSinks")?,
+        components,
+    }))
+}
+
+// The hardpoints and internals in a component, which can be either the mdf's
+// or an omnipod's. Slots and hp always come from the mdf.
+fn parse_component(
+    src: &XmlSource,
+    comp_elem: roxmltree::Node,
+    base_slots: i32,
+    hp: i32,
+    hardpoint_defs: &HardpointDefs,
+    internals: &[Internal],
+) -> Result<Component, Diagnostic> {
+    let mut hardpoint_count = BTreeMap::new();
+    let mut weapon_doors = vec![];
+    for hp in comp_elem
+        .children()
+        .filter(|x| x.tag_name().name() == "Hardpoint")
+    {
+        let id: i32 = src.parse_attr(hp, "ID")?;
+        let typ: u8 = src.parse_attr(hp, "Type")?;
+        let slots = *hardpoint_defs.slot_count.get(&id).ok_or_else(|| {
+            src.error_at(
+                hp,
+                format!("hardpoint {} is not in the hardpoints file", id),
+            )
+        })?;
+        hardpoint_count.insert(typ, slots);
+        if let Some(door) = hardpoint_defs.doors.get(&id) {
+            weapon_doors.push(WeaponDoor {
+                hardpoint_id: id,
+                hardpoint_kind: typ,
+                door_set_id: door.door_set_id,
+                open_duration: door.open_duration,
+                close_duration: door.close_duration,
+                delay_before_close: door.delay_before_close,
+            });
+        }
+    }
+    let mut internal_ids: Vec<i32> = vec![];
+    let mut internal_slots = 0;
+    for internal in comp_elem
+        .children()
+        .filter(|x| x.tag_name().name() == "Internal")
+    {
+        let iid: i32 = src.parse_attr(internal, "ItemID")?;
+        let int = internals.iter().find(|int| int.id == iid).ok_or_else(|| {
+            src.error_at(
+                internal,
+                format!("internal {} is not in Internals.xml", iid),
+            )
+        })?;
+        internal_ids.push(iid);
+        internal_slots += int.slots;
+    }
+    Ok(Component {
+        base_slots,
+        effective_slots: base_slots - internal_slots,
+        hp,
+        internal_ids,
+        hardpoint_count,
+        can_equip_ecm: match comp_elem.attribute("CanEquipECM") {
+            Some(_) => src.parse_attr::<i32>(comp_elem, "CanEquipECM")? != 0,
+            None => false,
+        },
+        weapon_doors,
+    })
+}
+
+fn parse_quirk(src: &XmlSource, q: roxmltree::Node) -> Result<(String, f32), Diagnostic> {
+    Ok((
+        src.attr(q, "name")?.to_string(),
+        src.parse_attr(q, "value")?,
+    ))
+}
+
+// The `*-omnipods.xml` of a Clan OmniMech chassis. Pods get their slots and hp
+// from whichever of the chassis' variants has that location.
+fn parse_omnipods(
+    src: &XmlSource,
+    doc: &roxmltree::Document,
+    chassis: &str,
+    variants: &[Variant],
+    hardpoint_defs: &HardpointDefs,
+    internals: &[Internal],
+    diags: &mut Diagnostics,
+) -> Result<(Vec<OmniPod>, Vec<OmniPodSet>), Diagnostic> {
+    let omnipods_elem = src.root(doc, "OmniPods")?;
+
+    let mut pods = vec![];
+    let mut sets = vec![];
+    for set_elem in omnipods_elem.children().filter(|x| x.is_element()) {
+        if let Err(e) = src.expect_tag(set_elem, "Set") {
+            diags.push(e);
+            continue;
+        }
+        let set_name = match diags.ok(src.attr(set_elem, "name")) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        let mut bonuses = BTreeMap::new();
+        for child in set_elem.children().filter(|x| x.is_element()) {
+            match child.tag_name().name() {
+                "SetBonuses" => {
+                    for bonus in child.children().filter(|x| x.is_element()) {
+                        bonuses.extend(diags.ok(parse_set_bonus(src, bonus)));
+                    }
+                }
+                "component" => {
+                    let pod = parse_omnipod(
+                        src,
+                        child,
+                        chassis,
+                        &set_name,
+                        variants,
+                        hardpoint_defs,
+                        internals,
+                    );
+                    pods.extend(diags.ok(pod));
+                }
+                other => {
+                    diags.push(
+                        src.error_at(child, format!("unknown omnipod set element <{}>", other)),
+                    );
+                }
+            }
+        }
+        sets.push(OmniPodSet {
+            chassis: chassis.to_string(),
+            name: set_name,
+            bonuses,
+        });
+    }
+
+    Ok((pods, sets))
+}
+
+// pieces -> quirks
+fn parse_set_bonus(
+    src: &XmlSource,
+    bonus: roxmltree::Node,
+) -> Result<(i32, Vec<(String, f32)>), Diagnostic> {
+    src.expect_tag(bonus, "Bonus")?;
+    let pieces: i32 = src.parse_attr(bonus, "PieceCount")?;
+    if !(2..=8).contains(&pieces) {
+        return Err(src.error_at(
+            bonus,
+            format!("a set bonus for {} pieces makes no sense", pieces),
+        ));
+    }
+    let quirks = bonus
+        .children()
+        .filter(|x| x.is_element())
+        .map(|q| src.expect_tag(q, "Quirk").and_then(|_| parse_quirk(src, q)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((pieces, quirks))
+}
+
+fn parse_omnipod(
+    src: &XmlSource,
+    pod_elem: roxmltree::Node,
+    chassis: &str,
+    set_name: &str,
+    variants: &[Variant],
+    hardpoint_defs: &HardpointDefs,
+    internals: &[Internal],
+) -> Result<OmniPod, Diagnostic> {
+    let location = src.attr(pod_elem, "name")?;
+    let chassis_comp = variants
+        .iter()
+        .find_map(|v| v.components.get(location))
+        .ok_or_else(|| {
+            src.error_at(
+                pod_elem,
+                format!("no variant of {} has a {}", chassis, location),
+            )
+        })?;
+    let quirks = pod_elem
+        .children()
+        .filter(|x| x.tag_name().name() == "Quirk")
+        .map(|q| parse_quirk(src, q))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(OmniPod {
+        chassis: chassis.to_string(),
+        set: set_name.to_string(),
+        location: location.to_string(),
+        component: parse_component(
+            src,
+            pod_elem,
+            chassis_comp.base_slots,
+            chassis_comp.hp,
+            hardpoint_defs,
+            internals,
+        )?,
+        quirks,
+    })
 }
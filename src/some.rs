@@ -6,7 +6,7 @@ use std::collections::BTreeMap;
 use std::collections::HashMap;
 // urls: https://mech.nav-alpha.com/php/fetch_quirks.php
 
-use crate::mwo_types::{HardpointKind, Variant, Weapon};
+use crate::mwo_types::{Engine, HardpointKind, Upgrade, UpgradeKind, Variant, Weapon};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(crate) enum TriState {
@@ -28,6 +28,8 @@ pub(crate) struct FitStatus {
 #[allow(unused_variables, unreachable_code)]
 pub(crate) fn stuffs(
     weapons: &[Weapon],
+    engines: &[Engine],
+    upgrades: &[Upgrade],
     mech_variants: &[Variant],
     mech_map: &HashMap<String, Variant>,
     chosen_weapon: &str,
@@ -63,8 +65,9 @@ pub(crate) fn stuffs(
         // Tonnage
         let mut tons_ok = true;
         let mut mech_free_tons = m.max_tons as f32;
-        mech_free_tons -= m.max_tons as f32 * 0.05; // 5% for endo-steel structure;
-        mech_free_tons -= 8.0 - 2.5; // urbie engine +8 heatsinks (todo: add real XL'est engine)
+        // 5% for endo-steel structure, if the data has none
+        mech_free_tons -= lightest_structure_tons(upgrades, m).unwrap_or(m.max_tons as f32 * 0.05);
+        mech_free_tons -= lightest_engine_tons(engines, m).unwrap_or(8.0 - 2.5); // urbie engine +8 heatsinks
 
         if mech_free_tons < equip_tons {
             tons_ok = false;
@@ -178,5 +181,30 @@ pub(crate) fn stuffs(
     }
 
     return result;
+}
+
+// The lightest engine the mech can take, plus the heat sinks it needs on top
+// to get to the minimum of 10.
+fn lightest_engine_tons(engines: &[Engine], mech: &Variant) -> Option<f32> {
+    engines
+        .iter()
+        .filter(|e| e.faction == mech.affiliation)
+        .filter(|e| e.rating >= mech.engine_min && e.rating <= mech.engine_max)
+        .map(|e| e.tons + std::cmp::max(0, 10 - e.heat_sinks) as f32)
+        .min_by(f32::total_cmp)
+}
 
+// What the internal structure weighs with the lightest structure upgrade the
+// mech can take.
+fn lightest_structure_tons(upgrades: &[Upgrade], mech: &Variant) -> Option<f32> {
+    upgrades
+        .iter()
+        .filter(|u| u.faction == mech.affiliation)
+        .filter_map(|u| match u.kind {
+            UpgradeKind::Structure {
+                tons_per_mech_ton, ..
+            } => Some(mech.max_tons as f32 * tons_per_mech_ton),
+            _ => None,
+        })
+        .min_by(f32::total_cmp)
 }